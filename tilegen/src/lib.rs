@@ -8,6 +8,10 @@ pub use regions::rect::*;
 pub use regions::region::*;
 
 pub use tiles::context::*;
+pub use tiles::diff::*;
+pub use tiles::fov::*;
 pub use tiles::grid::*;
+pub use tiles::lint::*;
+pub use tiles::prefab::*;
 pub use tiles::replacement::*;
 pub use tiles::replacement_rule::*;
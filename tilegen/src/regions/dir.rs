@@ -39,13 +39,18 @@ impl TileDir {
     }
 
     /// Randomly pick a direction using weights. Only weighted directions are used.
+    /// Weights are relative to each other and do not need to add up to 1
     pub fn rnd_weighted(weights: &HashMap<TileDir, f32>, rng: &mut impl Rng) -> TileDir {
-        let rnd = rng.random::<f32>();
+        let total: f32 = weights.values().sum();
+        let rnd = rng.random::<f32>() * total;
         let mut running_weight = 0.0;
-        for (dir, weight) in weights {
-            running_weight += weight;
-            if rnd < running_weight {
-                return *dir;
+        // walk in a fixed order so that the same rng always gives the same direction
+        for dir in Self::vec() {
+            if let Some(weight) = weights.get(&dir) {
+                running_weight += weight;
+                if rnd < running_weight {
+                    return dir;
+                }
             }
         }
         return TileDir::default();
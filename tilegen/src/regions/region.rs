@@ -24,6 +24,28 @@ pub struct TileExits {
     down: Vec<TileLine>,
 }
 
+#[allow(unused)]
+impl TileExits {
+    /// Get the exits on one side
+    pub fn get(&self, dir: TileDir) -> &Vec<TileLine> {
+        match dir {
+            TileDir::Left => &self.left,
+            TileDir::Right => &self.right,
+            TileDir::Up => &self.up,
+            TileDir::Down => &self.down,
+        }
+    }
+
+    /// Total number of exits on all sides
+    pub fn len(&self) -> usize {
+        self.left.len() + self.right.len() + self.up.len() + self.down.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 #[allow(unused)]
 impl<T> TileRegion<T>
 where
@@ -112,6 +134,16 @@ where
         }
     }
 
+    /// Add an exit along one side of the region
+    pub fn add_exit(&mut self, dir: TileDir, exit: TileLine) {
+        match dir {
+            TileDir::Left => self.exits.left.push(exit),
+            TileDir::Right => self.exits.right.push(exit),
+            TileDir::Up => self.exits.up.push(exit),
+            TileDir::Down => self.exits.down.push(exit),
+        }
+    }
+
     /// Add all edges as exits
     pub fn add_all_exits(&mut self) {
         TileDir::vec().iter().map(|d| {
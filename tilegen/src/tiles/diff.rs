@@ -0,0 +1,263 @@
+use std::fmt::{Debug, Display};
+
+use super::grid::TileGrid;
+
+/// A single tile that differs between two grids
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileChange<T: Clone + PartialEq + Eq> {
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+    pub from: Option<T>,
+    pub to: Option<T>,
+}
+
+/// The difference between two grids. Can be applied to the original grid to get the new one, or
+/// reverted from the new grid to get back to the original
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileDiff<T: Clone + PartialEq + Eq> {
+    /// width, height, depth
+    pub from_size: (usize, usize, usize),
+    /// width, height, depth
+    pub to_size: (usize, usize, usize),
+    pub changes: Vec<TileChange<T>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// The grid is not the size the patch was made from
+    SizeMismatch {
+        expected: (usize, usize, usize),
+        found: (usize, usize, usize),
+    },
+    /// A tile in the grid is not what the patch expected it to be
+    Conflict { x: usize, y: usize, z: usize },
+}
+
+#[allow(unused)]
+impl<T> TileDiff<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    /// Nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.from_size == self.to_size
+    }
+
+    pub fn is_resized(&self) -> bool {
+        self.from_size != self.to_size
+    }
+
+    /// Get a diff that undoes this one
+    pub fn inverse(&self) -> TileDiff<T> {
+        TileDiff {
+            from_size: self.to_size,
+            to_size: self.from_size,
+            changes: self
+                .changes
+                .iter()
+                .map(|c| TileChange {
+                    x: c.x,
+                    y: c.y,
+                    z: c.z,
+                    from: c.to.clone(),
+                    to: c.from.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Apply this to a grid. The grid must match the "from" side of the diff
+    pub fn apply(&self, grid: &mut TileGrid<T>) -> Result<(), PatchError> {
+        let found = (grid.width(), grid.height(), grid.depth());
+        if found != self.from_size {
+            return Err(PatchError::SizeMismatch {
+                expected: self.from_size,
+                found,
+            });
+        }
+        for change in &self.changes {
+            if grid.get_tile(change.x, change.y, change.z) != change.from.as_ref() {
+                return Err(PatchError::Conflict {
+                    x: change.x,
+                    y: change.y,
+                    z: change.z,
+                });
+            }
+        }
+
+        // grow first so that every change has somewhere to go, then shrink once they're written
+        let (from_w, from_h, from_d) = self.from_size;
+        let (to_w, to_h, to_d) = self.to_size;
+        grid.resize(from_w.max(to_w), from_h.max(to_h), from_d.max(to_d));
+        for change in &self.changes {
+            grid[change.x][change.y][change.z] = change.to.clone();
+        }
+        grid.resize(to_w, to_h, to_d);
+        Ok(())
+    }
+
+    /// Undo this on a grid. The grid must match the "to" side of the diff
+    pub fn revert(&self, grid: &mut TileGrid<T>) -> Result<(), PatchError> {
+        self.inverse().apply(grid)
+    }
+}
+
+#[allow(unused)]
+impl<T> TileGrid<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    /// Get every tile that is different in `other`. Grids of different sizes are compared as if
+    /// the space outside of each of them were empty
+    pub fn diff(&self, other: &TileGrid<T>) -> TileDiff<T> {
+        let width = self.width().max(other.width());
+        let height = self.height().max(other.height());
+        let depth = self.depth().max(other.depth());
+
+        let mut changes = Vec::new();
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let from = self.get_tile(x, y, z);
+                    let to = other.get_tile(x, y, z);
+                    if from != to {
+                        changes.push(TileChange {
+                            x,
+                            y,
+                            z,
+                            from: from.cloned(),
+                            to: to.cloned(),
+                        });
+                    }
+                }
+            }
+        }
+
+        TileDiff {
+            from_size: (self.width(), self.height(), self.depth()),
+            to_size: (other.width(), other.height(), other.depth()),
+            changes,
+        }
+    }
+}
+
+impl<T> Display for TileDiff<T>
+where
+    T: Clone + PartialEq + Eq + Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_resized() {
+            let (w0, h0, d0) = self.from_size;
+            let (w1, h1, d1) = self.to_size;
+            writeln!(f, "size: {w0}x{h0}x{d0} -> {w1}x{h1}x{d1}")?;
+        }
+
+        let mut layer = None;
+        for change in &self.changes {
+            if layer != Some(change.z) {
+                writeln!(f, "layer {}:", change.z)?;
+                layer = Some(change.z);
+            }
+            writeln!(
+                f,
+                "  {},{}: {} -> {}",
+                change.x,
+                change.y,
+                describe(&change.from),
+                describe(&change.to)
+            )?;
+        }
+
+        if self.is_empty() {
+            writeln!(f, "no changes")?;
+        }
+        Ok(())
+    }
+}
+
+fn describe<T: Debug>(tile: &Option<T>) -> String {
+    match tile {
+        Some(t) => format!("{:?}", t),
+        None => "_".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> TileGrid<u8> {
+        let mut grid = TileGrid::empty(3, 2, 1);
+        grid[0][0][0] = Some(1);
+        grid[1][1][0] = Some(2);
+        grid
+    }
+
+    #[test]
+    fn diff_same() {
+        let diff = grid().diff(&grid());
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn diff_changes() {
+        let mut other = grid();
+        other[0][0][0] = Some(3);
+        other[1][1][0] = None;
+        let diff = grid().diff(&other);
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(
+            diff.changes[0],
+            TileChange {
+                x: 0,
+                y: 0,
+                z: 0,
+                from: Some(1),
+                to: Some(3)
+            }
+        );
+        assert_eq!(
+            format!("{diff}"),
+            "layer 0:\n  0,0: 1 -> 3\n  1,1: 2 -> _\n"
+        );
+    }
+
+    #[test]
+    fn apply_and_revert() {
+        let original = grid();
+        let mut other = original.pad(1, 0, 0, 0);
+        other.insert_layer();
+        other[2][0][1] = Some(5);
+
+        let diff = original.diff(&other);
+        assert!(diff.is_resized());
+
+        let mut patched = original.clone();
+        diff.apply(&mut patched).unwrap();
+        assert_eq!(patched, other);
+
+        diff.revert(&mut patched).unwrap();
+        assert_eq!(patched, original);
+    }
+
+    #[test]
+    fn apply_conflict() {
+        let mut other = grid();
+        other[2][0][0] = Some(4);
+        let diff = grid().diff(&other);
+
+        let mut conflicting = grid();
+        conflicting[2][0][0] = Some(9);
+        assert_eq!(
+            diff.apply(&mut conflicting),
+            Err(PatchError::Conflict { x: 2, y: 0, z: 0 })
+        );
+
+        let mut wrong_size = TileGrid::empty(1, 1, 1);
+        assert!(matches!(
+            diff.apply(&mut wrong_size),
+            Err(PatchError::SizeMismatch { .. })
+        ));
+    }
+}
@@ -0,0 +1,325 @@
+use crate::TilePoint;
+
+use super::grid::TileGrid;
+
+/// The set of tiles visible from an origin, indexed the same way as the grid (`[x][y]`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldOfView {
+    pub origin: TilePoint,
+    pub visible: Vec<Vec<bool>>,
+}
+
+#[allow(unused)]
+impl FieldOfView {
+    pub fn is_visible(&self, x: usize, y: usize) -> bool {
+        self.visible
+            .get(x)
+            .and_then(|col| col.get(y))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Get all visible points as a list
+    pub fn points(&self) -> Vec<TilePoint> {
+        let mut points = Vec::new();
+        for x in 0..self.visible.len() {
+            for y in 0..self.visible[x].len() {
+                if self.visible[x][y] {
+                    points.push(TilePoint::new(x, y));
+                }
+            }
+        }
+        points
+    }
+}
+
+#[allow(unused)]
+impl<T> TileGrid<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    /// Calculate which tiles on a layer can be seen from the origin using symmetric shadowcasting.
+    /// Opaque tiles are visible themselves but block sight of anything behind them.
+    /// Tiles outside of the grid are always treated as opaque.
+    pub fn field_of_view(
+        &self,
+        origin: TilePoint,
+        radius: usize,
+        layer: usize,
+        is_opaque: impl Fn(&Option<T>) -> bool,
+    ) -> FieldOfView {
+        let mut visible = vec![vec![false; self.height()]; self.width()];
+        if origin.x < self.width() && origin.y < self.height() {
+            visible[origin.x][origin.y] = true;
+            for quadrant in Quadrant::all() {
+                self.scan(&origin, quadrant, radius, layer, &is_opaque, &mut visible);
+            }
+        }
+
+        FieldOfView { origin, visible }
+    }
+
+    /// Check if two points can see each other on a layer. Opaque tiles at either end do not
+    /// block sight, only those in between. The result is the same regardless of argument order.
+    pub fn has_line_of_sight(
+        &self,
+        from: TilePoint,
+        to: TilePoint,
+        layer: usize,
+        is_opaque: impl Fn(&Option<T>) -> bool,
+    ) -> bool {
+        self.is_line_clear(&from, &to, layer, &is_opaque)
+            || self.is_line_clear(&to, &from, layer, &is_opaque)
+    }
+
+    /// Walk a bresenham line between two points, checking that nothing in between is opaque
+    fn is_line_clear(
+        &self,
+        from: &TilePoint,
+        to: &TilePoint,
+        layer: usize,
+        is_opaque: &impl Fn(&Option<T>) -> bool,
+    ) -> bool {
+        let (x1, y1) = (to.x as i32, to.y as i32);
+        let (mut x, mut y) = (from.x as i32, from.y as i32);
+        let dx = (x1 - x).abs();
+        let dy = -(y1 - y).abs();
+        let step_x = if x < x1 { 1 } else { -1 };
+        let step_y = if y < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+
+        loop {
+            if x == x1 && y == y1 {
+                return true;
+            }
+            if (x != from.x as i32 || y != from.y as i32)
+                && self.is_opaque_at(x, y, layer, is_opaque)
+            {
+                return false;
+            }
+
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += step_x;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    fn is_opaque_at(
+        &self,
+        x: i32,
+        y: i32,
+        layer: usize,
+        is_opaque: &impl Fn(&Option<T>) -> bool,
+    ) -> bool {
+        !self.contains(x, y) || is_opaque(&self[x as usize][y as usize][layer])
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        x >= 0 && y >= 0 && (x as usize) < self.width() && (y as usize) < self.height()
+    }
+
+    fn scan(
+        &self,
+        origin: &TilePoint,
+        quadrant: Quadrant,
+        radius: usize,
+        layer: usize,
+        is_opaque: &impl Fn(&Option<T>) -> bool,
+        visible: &mut [Vec<bool>],
+    ) {
+        let mut rows = vec![Row::new(1, Slope::new(-1, 1), Slope::new(1, 1))];
+        while let Some(mut row) = rows.pop() {
+            if row.depth > radius as i32 {
+                continue;
+            }
+
+            // None = no previous tile, Some(true) = previous was opaque
+            let mut prev_opaque: Option<bool> = None;
+            for col in row.min_col()..=row.max_col() {
+                let (x, y) = quadrant.transform(origin, row.depth, col);
+                let opaque = self.is_opaque_at(x, y, layer, is_opaque);
+                let in_range = row.depth * row.depth + col * col <= (radius * radius) as i32;
+
+                if in_range && (opaque || row.is_symmetric(col)) && self.contains(x, y) {
+                    visible[x as usize][y as usize] = true;
+                }
+                if prev_opaque == Some(true) && !opaque {
+                    row.start = Slope::from_tile(row.depth, col);
+                }
+                if prev_opaque == Some(false) && opaque {
+                    let mut next = row.next();
+                    next.end = Slope::from_tile(row.depth, col);
+                    rows.push(next);
+                }
+                prev_opaque = Some(opaque);
+            }
+
+            if prev_opaque == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    fn all() -> [Quadrant; 4] {
+        [
+            Quadrant::North,
+            Quadrant::East,
+            Quadrant::South,
+            Quadrant::West,
+        ]
+    }
+
+    /// Convert a row/column relative to this quadrant into grid coordinates
+    fn transform(&self, origin: &TilePoint, depth: i32, col: i32) -> (i32, i32) {
+        let (x, y) = (origin.x as i32, origin.y as i32);
+        match self {
+            Quadrant::North => (x + col, y - depth),
+            Quadrant::South => (x + col, y + depth),
+            Quadrant::East => (x + depth, y + col),
+            Quadrant::West => (x - depth, y + col),
+        }
+    }
+}
+
+/// An exact fraction so that slopes don't accumulate float error
+#[derive(Debug, Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Self { num, den }
+    }
+
+    /// The slope of the left edge of a tile
+    fn from_tile(depth: i32, col: i32) -> Self {
+        Self::new(2 * col - 1, 2 * depth)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    fn new(depth: i32, start: Slope, end: Slope) -> Self {
+        Self { depth, start, end }
+    }
+
+    fn next(&self) -> Self {
+        Self::new(self.depth + 1, self.start, self.end)
+    }
+
+    /// depth * start, rounding ties up
+    fn min_col(&self) -> i32 {
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    /// depth * end, rounding ties down
+    fn max_col(&self) -> i32 {
+        -(self.end.den - 2 * self.depth * self.end.num).div_euclid(2 * self.end.den)
+    }
+
+    /// Floor tiles are only revealed if their center is within the row's slopes
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num
+            && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walled_grid() -> TileGrid<bool> {
+        // 7x7 open room with a single pillar at 3,2
+        let mut grid = TileGrid::empty(7, 7, 1);
+        grid[3][2][0] = Some(true);
+        grid
+    }
+
+    fn opaque(tile: &Option<bool>) -> bool {
+        *tile == Some(true)
+    }
+
+    #[test]
+    fn fov_open_room() {
+        let grid: TileGrid<bool> = TileGrid::empty(5, 5, 1);
+        let fov = grid.field_of_view(TilePoint::new(2, 2), 10, 0, opaque);
+        assert_eq!(fov.points().len(), 25);
+    }
+
+    #[test]
+    fn fov_radius() {
+        let grid: TileGrid<bool> = TileGrid::empty(9, 9, 1);
+        let fov = grid.field_of_view(TilePoint::new(4, 4), 1, 0, opaque);
+        assert!(fov.is_visible(4, 4));
+        assert!(fov.is_visible(4, 3));
+        assert!(fov.is_visible(5, 4));
+        assert!(!fov.is_visible(5, 5));
+        assert!(!fov.is_visible(4, 6));
+    }
+
+    #[test]
+    fn fov_blocked_by_pillar() {
+        let grid = walled_grid();
+        let fov = grid.field_of_view(TilePoint::new(3, 4), 10, 0, opaque);
+        // the pillar itself can be seen
+        assert!(fov.is_visible(3, 2));
+        // but not what is directly behind it
+        assert!(!fov.is_visible(3, 1));
+        assert!(!fov.is_visible(3, 0));
+        assert!(fov.is_visible(0, 4));
+    }
+
+    #[test]
+    fn fov_symmetric() {
+        let grid = walled_grid();
+        for x in 0..grid.width() {
+            for y in 0..grid.height() {
+                if grid[x][y][0].is_some() {
+                    continue;
+                }
+                let fov = grid.field_of_view(TilePoint::new(x, y), 10, 0, opaque);
+                for point in fov.points() {
+                    if grid[point.x][point.y][0].is_some() {
+                        continue;
+                    }
+                    let other = grid.field_of_view(point, 10, 0, opaque);
+                    assert!(other.is_visible(x, y), "{x},{y} <-> {:?}", point);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn line_of_sight() {
+        let grid = walled_grid();
+        assert!(grid.has_line_of_sight(TilePoint::new(0, 0), TilePoint::new(6, 6), 0, opaque));
+        assert!(!grid.has_line_of_sight(TilePoint::new(3, 0), TilePoint::new(3, 6), 0, opaque));
+        assert!(!grid.has_line_of_sight(TilePoint::new(3, 6), TilePoint::new(3, 0), 0, opaque));
+        // endpoints are allowed to be opaque
+        assert!(grid.has_line_of_sight(TilePoint::new(3, 6), TilePoint::new(3, 2), 0, opaque));
+    }
+}
@@ -5,7 +5,7 @@ use rand::Rng;
 use super::replacement_rule::ReplacementRule;
 use super::replacer::replace_tiles;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TileGrid<T: Clone + PartialEq + Eq> {
     pub tiles: Vec<Vec<Vec<Option<T>>>>,
 }
@@ -44,6 +44,26 @@ where
         }
     }
 
+    /// Get the tile at a position, or nothing if the position is outside of the grid
+    pub fn get_tile(&self, x: usize, y: usize, z: usize) -> Option<&T> {
+        self.tiles
+            .get(x)
+            .and_then(|col| col.get(y))
+            .and_then(|cell| cell.get(z))
+            .and_then(|tile| tile.as_ref())
+    }
+
+    /// Change the size of the grid. Tiles outside of the new size are dropped and new space is empty
+    pub fn resize(&mut self, width: usize, height: usize, depth: usize) {
+        self.tiles.resize(width, vec![vec![None; depth]; height]);
+        for col in self.tiles.iter_mut() {
+            col.resize(height, vec![None; depth]);
+            for cell in col.iter_mut() {
+                cell.resize(depth, None);
+            }
+        }
+    }
+
     /// Wrap grid in padding
     pub fn pad(&self, top: u8, right: u8, bottom: u8, left: u8) -> TileGrid<T> {
         let width = self.width() + left as usize + right as usize;
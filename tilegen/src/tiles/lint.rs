@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::TilePoint;
+
+use super::context::TileContext;
+use super::grid::TileGrid;
+
+/// A rule that a generated grid is expected to follow
+#[derive(Clone, Debug)]
+pub enum GridInvariant<T: Clone + PartialEq + Eq> {
+    /// Every tile on the layer that `applies` to must pass the check
+    Tile {
+        name: &'static str,
+        layer: usize,
+        applies: fn(&TileContext<T>) -> bool,
+        check: fn(&TileContext<T>) -> bool,
+    },
+    /// No two tiles on the layer may share the same key. Tiles without a key are ignored
+    Unique {
+        name: &'static str,
+        layer: usize,
+        key: fn(&T) -> Option<u32>,
+    },
+    /// Free-form check over a layer of the grid, returning the position of every tile that breaks it
+    Grid {
+        name: &'static str,
+        layer: usize,
+        check: fn(&TileGrid<T>, usize) -> Vec<TilePoint>,
+    },
+}
+
+/// A tile which broke an invariant
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub invariant: &'static str,
+    pub x: usize,
+    pub y: usize,
+    pub z: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LintReport {
+    pub violations: Vec<Violation>,
+}
+
+#[allow(unused)]
+impl LintReport {
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Get the position of every violation of a specific invariant
+    pub fn points(&self, invariant: &str) -> Vec<TilePoint> {
+        self.violations
+            .iter()
+            .filter(|v| v.invariant == invariant)
+            .map(|v| TilePoint::new(v.x, v.y))
+            .collect()
+    }
+}
+
+impl Display for LintReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for violation in &self.violations {
+            writeln!(
+                f,
+                "{} at {},{} (layer {})",
+                violation.invariant, violation.x, violation.y, violation.z
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(unused)]
+impl<T> TileGrid<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    /// Check the grid against a set of invariants, reporting every tile which breaks one
+    pub fn lint(&self, invariants: &[GridInvariant<T>]) -> LintReport {
+        let mut violations = Vec::new();
+
+        for invariant in invariants {
+            match invariant {
+                GridInvariant::Tile {
+                    name,
+                    layer,
+                    applies,
+                    check,
+                } => {
+                    if *layer >= self.depth() {
+                        continue;
+                    }
+                    for x in 0..self.width() {
+                        for y in 0..self.height() {
+                            let ctx = TileContext {
+                                x: x as i32,
+                                y: y as i32,
+                                z: *layer as i32,
+                                tile: &self[x][y][*layer],
+                                grid: self,
+                            };
+                            if applies(&ctx) && !check(&ctx) {
+                                violations.push(Violation {
+                                    invariant: name,
+                                    x,
+                                    y,
+                                    z: *layer,
+                                });
+                            }
+                        }
+                    }
+                }
+                GridInvariant::Unique { name, layer, key } => {
+                    if *layer >= self.depth() {
+                        continue;
+                    }
+                    let mut seen: HashMap<u32, usize> = HashMap::new();
+                    let mut keyed = Vec::new();
+                    for x in 0..self.width() {
+                        for y in 0..self.height() {
+                            if let Some(k) = self[x][y][*layer].as_ref().and_then(key) {
+                                *seen.entry(k).or_default() += 1;
+                                keyed.push((k, x, y));
+                            }
+                        }
+                    }
+                    for (k, x, y) in keyed {
+                        if seen[&k] > 1 {
+                            violations.push(Violation {
+                                invariant: name,
+                                x,
+                                y,
+                                z: *layer,
+                            });
+                        }
+                    }
+                }
+                GridInvariant::Grid { name, layer, check } => {
+                    for point in check(self, *layer) {
+                        violations.push(Violation {
+                            invariant: name,
+                            x: point.x,
+                            y: point.y,
+                            z: *layer,
+                        });
+                    }
+                }
+            }
+        }
+
+        LintReport { violations }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1 = floor, 2 = wall, 10+ = switches
+    fn grid() -> TileGrid<u8> {
+        let mut grid = TileGrid::empty(3, 3, 1);
+        for x in 0..3 {
+            for y in 0..3 {
+                grid[x][y][0] = Some(2);
+            }
+        }
+        grid[1][1][0] = Some(1);
+        grid
+    }
+
+    fn invariants() -> Vec<GridInvariant<u8>> {
+        vec![
+            GridInvariant::Tile {
+                name: "floor is enclosed",
+                layer: 0,
+                applies: |ctx| *ctx == 1,
+                check: |ctx| {
+                    ctx.up().tile.is_some()
+                        && ctx.down().tile.is_some()
+                        && ctx.left().tile.is_some()
+                        && ctx.right().tile.is_some()
+                },
+            },
+            GridInvariant::Unique {
+                name: "unique switch",
+                layer: 0,
+                key: |t| if *t >= 10 { Some(*t as u32) } else { None },
+            },
+        ]
+    }
+
+    #[test]
+    fn lint_ok() {
+        let report = grid().lint(&invariants());
+        assert!(report.is_ok());
+    }
+
+    #[test]
+    fn lint_tile() {
+        let mut grid = grid();
+        grid[1][2][0] = None;
+        let report = grid.lint(&invariants());
+        assert_eq!(
+            report.points("floor is enclosed"),
+            vec![TilePoint::new(1, 1)]
+        );
+        assert_eq!(format!("{report}"), "floor is enclosed at 1,1 (layer 0)\n");
+    }
+
+    #[test]
+    fn lint_unique() {
+        let mut grid = grid();
+        grid[0][0][0] = Some(10);
+        grid[2][2][0] = Some(10);
+        grid[2][0][0] = Some(11);
+        let report = grid.lint(&invariants());
+        assert_eq!(
+            report.points("unique switch"),
+            vec![TilePoint::new(0, 0), TilePoint::new(2, 2)]
+        );
+    }
+
+    #[test]
+    fn lint_grid() {
+        let report = grid().lint(&[GridInvariant::Grid {
+            name: "no walls",
+            layer: 0,
+            check: |grid, layer| {
+                let mut points = Vec::new();
+                for x in 0..grid.width() {
+                    for y in 0..grid.height() {
+                        if grid[x][y][layer] == Some(2) {
+                            points.push(TilePoint::new(x, y));
+                        }
+                    }
+                }
+                points
+            },
+        }]);
+        assert_eq!(report.violations.len(), 8);
+    }
+}
@@ -1,5 +1,9 @@
 pub mod context;
+pub mod diff;
+pub mod fov;
 pub mod grid;
+pub mod lint;
+pub mod prefab;
 pub mod replacement;
 pub mod replacement_rule;
 pub mod replacer;
@@ -0,0 +1,382 @@
+use rand::prelude::*;
+
+use crate::{TileDir, TilePoint, TileRect};
+
+use super::grid::TileGrid;
+
+/// A small handcrafted grid that can be stamped into a larger one
+#[derive(Debug, Clone)]
+pub struct Prefab<T: Clone + PartialEq + Eq> {
+    pub grid: TileGrid<T>,
+    pub anchors: Vec<PrefabAnchor<T>>,
+}
+
+/// Rules describing where a prefab is allowed to be placed
+#[derive(Debug, Clone)]
+pub enum PrefabAnchor<T: Clone + PartialEq + Eq> {
+    /// An opening on the edge of the prefab. The tile just outside of it (in `dir`) must meet the condition
+    Socket {
+        point: TilePoint,
+        dir: TileDir,
+        layer: usize,
+        condition: fn(&Option<T>) -> bool,
+    },
+    /// Every tile covered by the prefab must meet the condition on the given layer.
+    /// The prefab is allowed to overwrite tiles on this layer.
+    Underlay {
+        layer: usize,
+        condition: fn(&Option<T>) -> bool,
+    },
+    /// Space around the prefab that must be empty on the given layer. Sockets are excluded
+    Clearance { margin: usize, layer: usize },
+}
+
+impl<T: Clone + PartialEq + Eq> PrefabAnchor<T> {
+    /// The layer of the grid the anchor checks
+    pub fn layer(&self) -> usize {
+        match self {
+            PrefabAnchor::Socket { layer, .. }
+            | PrefabAnchor::Underlay { layer, .. }
+            | PrefabAnchor::Clearance { layer, .. } => *layer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrefabError {
+    /// The prefab has more layers than the grid
+    TooManyLayers,
+    /// An anchor checks a layer the grid doesn't have
+    MissingLayer(usize),
+    /// The prefab would hang off the edge of the grid
+    OutOfBounds,
+    /// The prefab would overwrite an existing tile
+    Collision(TilePoint),
+    /// A tile under the prefab did not meet the underlay condition
+    MissingUnderlay(TilePoint),
+    /// A tile around the prefab was not empty
+    BlockedClearance(TilePoint),
+    /// A socket did not lead anywhere
+    UnconnectedSocket(TilePoint),
+    /// There was nowhere on the grid the prefab fit
+    NoSpace,
+}
+
+#[allow(unused)]
+impl<T> Prefab<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    pub fn new(grid: TileGrid<T>) -> Self {
+        Self {
+            grid,
+            anchors: Vec::new(),
+        }
+    }
+
+    pub fn with_anchor(mut self, anchor: PrefabAnchor<T>) -> Self {
+        self.anchors.push(anchor);
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.grid.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.grid.height()
+    }
+
+    /// Check if the prefab can be stamped with its top left at the given position
+    pub fn fits(&self, target: &TileGrid<T>, x: usize, y: usize) -> Result<(), PrefabError> {
+        if self.grid.depth() > target.depth() {
+            return Err(PrefabError::TooManyLayers);
+        }
+        if x + self.width() > target.width() || y + self.height() > target.height() {
+            return Err(PrefabError::OutOfBounds);
+        }
+
+        for anchor in &self.anchors {
+            if anchor.layer() >= target.depth() {
+                return Err(PrefabError::MissingLayer(anchor.layer()));
+            }
+
+            match anchor {
+                PrefabAnchor::Socket {
+                    point,
+                    dir,
+                    layer,
+                    condition,
+                } => {
+                    let exit = Self::socket_exit(x + point.x, y + point.y, dir);
+                    let connected = exit
+                        .filter(|p| p.x < target.width() && p.y < target.height())
+                        .map(|p| condition(&target[p.x][p.y][*layer]))
+                        .unwrap_or(false);
+                    if !connected {
+                        return Err(PrefabError::UnconnectedSocket(*point));
+                    }
+                }
+                PrefabAnchor::Underlay { layer, condition } => {
+                    for px in 0..self.width() {
+                        for py in 0..self.height() {
+                            if self.is_occupied(px, py)
+                                && !condition(&target[x + px][y + py][*layer])
+                            {
+                                return Err(PrefabError::MissingUnderlay(TilePoint::new(
+                                    x + px,
+                                    y + py,
+                                )));
+                            }
+                        }
+                    }
+                }
+                PrefabAnchor::Clearance { margin, layer } => {
+                    let sockets = self.socket_exits(x, y);
+                    let x0 = x.saturating_sub(*margin);
+                    let y0 = y.saturating_sub(*margin);
+                    let x1 = (x + self.width() + margin).min(target.width());
+                    let y1 = (y + self.height() + margin).min(target.height());
+                    for tx in x0..x1 {
+                        for ty in y0..y1 {
+                            let inside = tx >= x
+                                && tx < x + self.width()
+                                && ty >= y
+                                && ty < y + self.height();
+                            let point = TilePoint::new(tx, ty);
+                            if !inside
+                                && !sockets.contains(&point)
+                                && target[tx][ty][*layer].is_some()
+                            {
+                                return Err(PrefabError::BlockedClearance(point));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // anything not on an underlay layer has to land on empty space
+        for z in 0..self.grid.depth() {
+            if self.is_underlay_layer(z) {
+                continue;
+            }
+            for px in 0..self.width() {
+                for py in 0..self.height() {
+                    if self.grid[px][py][z].is_some() && target[x + px][y + py][z].is_some() {
+                        return Err(PrefabError::Collision(TilePoint::new(x + px, y + py)));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy the prefab into the grid with its top left at the given position.
+    /// Empty prefab tiles leave the grid untouched
+    pub fn stamp(
+        &self,
+        target: &mut TileGrid<T>,
+        x: usize,
+        y: usize,
+    ) -> Result<TileRect, PrefabError> {
+        self.fits(target, x, y)?;
+
+        for px in 0..self.width() {
+            for py in 0..self.height() {
+                for z in 0..self.grid.depth() {
+                    if let Some(tile) = &self.grid[px][py][z] {
+                        target[x + px][y + py][z] = Some(tile.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(TileRect::new(x, y, x + self.width(), y + self.height()))
+    }
+
+    /// Get every position the prefab could be stamped at
+    pub fn candidates(&self, target: &TileGrid<T>) -> Vec<TilePoint> {
+        let mut points = Vec::new();
+        let max_x = target.width().saturating_sub(self.width());
+        let max_y = target.height().saturating_sub(self.height());
+        for x in 0..=max_x {
+            for y in 0..=max_y {
+                if self.fits(target, x, y).is_ok() {
+                    points.push(TilePoint::new(x, y));
+                }
+            }
+        }
+        points
+    }
+
+    /// Stamp the prefab at a random position where it fits
+    pub fn stamp_rnd(
+        &self,
+        target: &mut TileGrid<T>,
+        rng: &mut impl Rng,
+    ) -> Result<TileRect, PrefabError> {
+        match self.candidates(target).choose(rng) {
+            Some(point) => self.stamp(target, point.x, point.y),
+            None => Err(PrefabError::NoSpace),
+        }
+    }
+
+    fn is_occupied(&self, x: usize, y: usize) -> bool {
+        self.grid[x][y].iter().any(|t| t.is_some())
+    }
+
+    fn is_underlay_layer(&self, layer: usize) -> bool {
+        self.anchors
+            .iter()
+            .any(|a| matches!(a, PrefabAnchor::Underlay { layer: l, .. } if *l == layer))
+    }
+
+    fn socket_exits(&self, x: usize, y: usize) -> Vec<TilePoint> {
+        self.anchors
+            .iter()
+            .filter_map(|a| match a {
+                PrefabAnchor::Socket { point, dir, .. } => {
+                    Self::socket_exit(x + point.x, y + point.y, dir)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn socket_exit(x: usize, y: usize, dir: &TileDir) -> Option<TilePoint> {
+        match dir {
+            TileDir::Left => x.checked_sub(1).map(|x| TilePoint::new(x, y)),
+            TileDir::Right => Some(TilePoint::new(x + 1, y)),
+            TileDir::Up => y.checked_sub(1).map(|y| TilePoint::new(x, y)),
+            TileDir::Down => Some(TilePoint::new(x, y + 1)),
+        }
+    }
+}
+
+#[allow(unused)]
+impl<T> TileGrid<T>
+where
+    T: Clone + PartialEq + Eq,
+{
+    /// Randomly stamp each prefab somewhere it fits, in order.
+    /// Returns the placement (or reason for failure) of each prefab
+    pub fn stamp_prefabs(
+        &mut self,
+        prefabs: &[Prefab<T>],
+        rng: &mut impl Rng,
+    ) -> Vec<Result<TileRect, PrefabError>> {
+        prefabs.iter().map(|p| p.stamp_rnd(self, rng)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    use super::*;
+
+    // 1 = floor, 2 = furniture
+    fn room() -> Prefab<u8> {
+        let mut grid = TileGrid::empty(2, 2, 2);
+        grid[0][0][0] = Some(1);
+        grid[1][0][0] = Some(1);
+        grid[0][1][0] = Some(1);
+        grid[1][1][0] = Some(1);
+        grid[1][1][1] = Some(2);
+        Prefab::new(grid)
+    }
+
+    #[test]
+    fn stamp_into_empty() {
+        let mut target = TileGrid::empty(4, 4, 2);
+        let rect = room().stamp(&mut target, 1, 1).unwrap();
+        assert_eq!(rect, TileRect::new(1, 1, 3, 3));
+        assert_eq!(target[2][2][1], Some(2));
+        assert_eq!(target[0][0][0], None);
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        let target = TileGrid::empty(4, 4, 2);
+        assert_eq!(room().fits(&target, 3, 0), Err(PrefabError::OutOfBounds));
+        let shallow = TileGrid::empty(4, 4, 1);
+        assert_eq!(room().fits(&shallow, 0, 0), Err(PrefabError::TooManyLayers));
+        let deep_anchor = room().with_anchor(PrefabAnchor::Clearance {
+            margin: 1,
+            layer: 2,
+        });
+        assert_eq!(
+            deep_anchor.fits(&target, 0, 0),
+            Err(PrefabError::MissingLayer(2))
+        );
+    }
+
+    #[test]
+    fn collision() {
+        let mut target = TileGrid::empty(4, 4, 2);
+        target[1][0][0] = Some(1);
+        assert_eq!(
+            room().fits(&target, 0, 0),
+            Err(PrefabError::Collision(TilePoint::new(1, 0)))
+        );
+    }
+
+    #[test]
+    fn underlay() {
+        let prefab = room().with_anchor(PrefabAnchor::Underlay {
+            layer: 0,
+            condition: |t| *t == Some(1),
+        });
+        let mut target = TileGrid::empty(4, 4, 2);
+        target[0][0][0] = Some(1);
+        target[1][0][0] = Some(1);
+        target[0][1][0] = Some(1);
+        assert_eq!(
+            prefab.fits(&target, 0, 0),
+            Err(PrefabError::MissingUnderlay(TilePoint::new(1, 1)))
+        );
+        target[1][1][0] = Some(1);
+        assert!(prefab.stamp(&mut target, 0, 0).is_ok());
+    }
+
+    #[test]
+    fn clearance_and_sockets() {
+        let prefab = room()
+            .with_anchor(PrefabAnchor::Clearance {
+                margin: 1,
+                layer: 0,
+            })
+            .with_anchor(PrefabAnchor::Socket {
+                point: TilePoint::new(0, 0),
+                dir: TileDir::Left,
+                layer: 0,
+                condition: |t| *t == Some(1),
+            });
+        let mut target = TileGrid::empty(5, 5, 2);
+        assert_eq!(
+            prefab.fits(&target, 2, 2),
+            Err(PrefabError::UnconnectedSocket(TilePoint::new(0, 0)))
+        );
+        // the socket leads to floor, which is allowed inside the margin
+        target[1][2][0] = Some(1);
+        assert!(prefab.fits(&target, 2, 2).is_ok());
+        // anything else inside the margin is not
+        target[1][3][0] = Some(1);
+        assert_eq!(
+            prefab.fits(&target, 2, 2),
+            Err(PrefabError::BlockedClearance(TilePoint::new(1, 3)))
+        );
+    }
+
+    #[test]
+    fn scatter() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut target = TileGrid::empty(3, 2, 2);
+        let results = target.stamp_prefabs(&[room(), room()], &mut rng);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1], Err(PrefabError::NoSpace));
+    }
+}
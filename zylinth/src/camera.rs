@@ -1,7 +1,7 @@
-use bevy::color::palettes::tailwind::GRAY_200;
 use bevy::prelude::*;
-use bevy_lit::prelude::{AmbientLight2d, Lighting2dSettings};
+use bevy_lit::prelude::Lighting2dSettings;
 
+use crate::map::default_ambient;
 use crate::player::Player;
 
 pub struct CameraSetup;
@@ -19,10 +19,7 @@ fn camera(mut commands: Commands) {
         Camera2d,
         Msaa::Off,
         Lighting2dSettings { ..default() },
-        AmbientLight2d {
-            brightness: 0.15,
-            color: Color::from(GRAY_200),
-        },
+        default_ambient(),
     ));
 }
 
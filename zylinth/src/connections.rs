@@ -1,17 +1,33 @@
+use std::collections::HashMap;
+
 use bevy::color::palettes::css::REBECCA_PURPLE;
 use bevy::color::palettes::tailwind::INDIGO_600;
 use bevy::prelude::*;
 
-use crate::defs::{ControlLink, ControlSource, ControlTarget};
+use crate::defs::{ControlLink, ControlSource, ControlTarget, MultipleInputs};
+use crate::gate::{Gate, MuxSelector};
+use crate::map::{GateKind, NewMap};
 use crate::player::Player;
 use crate::selection::Selectable;
 
+/// Everything a connection can start or end on
+type ConnectionEnd = (
+    &'static Selectable,
+    Entity,
+    Option<&'static ControlSource>,
+    Option<&'static ControlTarget>,
+    Option<&'static MuxSelector>,
+);
+
 #[derive(Resource, Default, Debug)]
 pub struct ConnectionState {
     source_entity: Option<Entity>,
     source_id: Option<u8>,
     target_entity: Option<Entity>,
     target_id: Option<u8>,
+    /// The multiplexer whose selector the connection started on, by gate id
+    selector_entity: Option<Entity>,
+    selector_id: Option<u8>,
 }
 
 #[derive(States, Debug, Default, Clone, PartialEq, Eq, Hash)]
@@ -50,6 +66,7 @@ impl Plugin for ConnectionsPlugin {
 
         app.add_systems(Startup, line_setup);
         app.add_systems(Update, propagate_source_to_target);
+        app.add_systems(Update, cancel_connection);
         app.add_systems(
             Update,
             start_connection.run_if(in_state(ConnectionMode::Default)),
@@ -63,19 +80,14 @@ impl Plugin for ConnectionsPlugin {
 }
 
 fn start_connection(
-    selectables: Query<(
-        &Selectable,
-        Entity,
-        Option<&ControlSource>,
-        Option<&ControlTarget>,
-    )>,
+    selectables: Query<ConnectionEnd>,
     input: Res<ButtonInput<KeyCode>>,
     mut connection_state: ResMut<ConnectionState>,
     mut next_mode: ResMut<NextState<ConnectionMode>>,
     player: Query<&GlobalTransform, With<Player>>,
 ) {
     if input.any_just_pressed([KeyCode::KeyV, KeyCode::ControlRight]) {
-        for (selectable, entity, source, target) in selectables.iter() {
+        for (selectable, entity, source, target, selector) in selectables.iter() {
             if selectable.selected {
                 if let Some(source) = source {
                     connection_state.source_entity = Some(entity);
@@ -85,9 +97,14 @@ fn start_connection(
                     connection_state.target_entity = Some(entity);
                     connection_state.target_id = Some(target.id);
                 }
+                if let Some(selector) = selector {
+                    connection_state.selector_entity = Some(entity);
+                    connection_state.selector_id = Some(selector.gate);
+                }
 
                 if connection_state.source_entity.is_some()
                     || connection_state.target_entity.is_some()
+                    || connection_state.selector_entity.is_some()
                 {
                     // change state to connection active
                     next_mode.set(ConnectionMode::MakingConnection);
@@ -99,6 +116,18 @@ fn start_connection(
     }
 }
 
+/// Drop any half made connection when the map changes, since it points at tiles that are gone
+fn cancel_connection(
+    mut ev_newmap: EventReader<NewMap>,
+    mut connection_state: ResMut<ConnectionState>,
+    mut next_mode: ResMut<NextState<ConnectionMode>>,
+) {
+    for _ in ev_newmap.read() {
+        *connection_state = ConnectionState::default();
+        next_mode.set(ConnectionMode::Default);
+    }
+}
+
 fn line_setup(mut config_store: ResMut<GizmoConfigStore>) {
     let (config, _) = config_store.config_mut::<TempConnectionLine>();
     config.line_style = GizmoLineStyle::Dotted;
@@ -116,7 +145,8 @@ fn render_temp_connection(
 ) {
     let from_entity = connection_state
         .source_entity
-        .or(connection_state.target_entity);
+        .or(connection_state.target_entity)
+        .or(connection_state.selector_entity);
     if let Some(from_entity) = from_entity {
         let from_transform = entities.get(from_entity).unwrap();
         let player_transform = player.get_single().unwrap();
@@ -136,58 +166,24 @@ fn render_temp_connection(
 }
 
 fn end_connection(
-    selectables: Query<(
-        &Selectable,
-        Entity,
-        Option<&ControlSource>,
-        Option<&ControlTarget>,
-    )>,
+    selectables: Query<ConnectionEnd>,
     input: Res<ButtonInput<KeyCode>>,
     mut connection_state: ResMut<ConnectionState>,
     mut next_mode: ResMut<NextState<ConnectionMode>>,
     mut commands: Commands,
 ) {
     if input.any_just_pressed([KeyCode::KeyV, KeyCode::ControlRight]) {
-        for (selectable, entity, source, target) in selectables.iter() {
+        for (selectable, entity, source, target, selector) in selectables.iter() {
             if selectable.selected {
-                match (
-                    connection_state.source_id,
-                    connection_state.target_id,
-                    source,
-                    target,
-                ) {
-                    // there is at least a from source and only a to target
-                    (Some(source_id), _, None, Some(target))
-                        if connection_state.source_entity != Some(entity) =>
-                    {
-                        debug!("Created connection from {source_id} to {}", target.id);
-                        commands.spawn(ControlLink::new(source_id, target.id));
-                    }
-                    // there is at least a from target, and only a to source
-                    (_, Some(target_id), Some(source), None)
-                        if connection_state.target_entity != Some(entity) =>
-                    {
-                        debug!("Created connection from {} to {target_id}", source.id);
-                        commands.spawn(ControlLink::new(source.id, target_id));
+                match connection_link(&connection_state, entity, source, target, selector) {
+                    Some(link) => {
+                        debug!("Created connection from {} to {}", link.source, link.target);
+                        commands.spawn(link);
                     }
-                    // there is only a from source, and at least a to target
-                    (Some(source_id), None, _, Some(target))
-                        if connection_state.source_entity != Some(entity) =>
-                    {
-                        debug!("Created connection from {source_id} to {}", target.id);
-                        commands.spawn(ControlLink::new(source_id, target.id));
-                    }
-                    // there is only a from target, and at least a to source
-                    (None, Some(target_id), Some(source), _)
-                        if connection_state.target_entity != Some(entity) =>
-                    {
-                        debug!("Created connection from {} to {target_id}", source.id);
-                        commands.spawn(ControlLink::new(source.id, target_id));
-                    }
-                    _ => {
+                    None => {
                         warn!(
-                            "Found ambiguous or disallowed case for connection: {:?} {:?} {:?} {:?}",
-                            connection_state.source_id, connection_state.target_id, source, target,
+                            "Found ambiguous or disallowed case for connection: {:?} {:?} {:?}",
+                            connection_state, source, target,
                         );
                     }
                 }
@@ -196,50 +192,132 @@ fn end_connection(
 
         // TODO: turn off temp
 
-        connection_state.source_entity = None;
-        connection_state.source_id = None;
-        connection_state.target_entity = None;
-        connection_state.target_id = None;
+        *connection_state = ConnectionState::default();
         next_mode.set(ConnectionMode::Default);
     }
 }
 
+/// The link made by ending a connection on an entity. Whatever the connection started on is wired
+/// into the end if it has an output and the end takes an input, otherwise the end is wired into it
+fn connection_link(
+    state: &ConnectionState,
+    entity: Entity,
+    source: Option<&ControlSource>,
+    target: Option<&ControlTarget>,
+    selector: Option<&MuxSelector>,
+) -> Option<ControlLink> {
+    let started_on = [
+        state.source_entity,
+        state.target_entity,
+        state.selector_entity,
+    ];
+    if started_on.contains(&Some(entity)) {
+        return None;
+    }
+
+    let link = match (state.source_id, target, selector) {
+        (Some(source_id), Some(target), _) => ControlLink::new(source_id, target.id),
+        (Some(source_id), None, Some(selector)) => ControlLink::selector(source_id, selector.gate),
+        _ => match (source, state.target_id, state.selector_id) {
+            (Some(source), Some(target_id), _) => ControlLink::new(source.id, target_id),
+            (Some(source), None, Some(gate)) => ControlLink::selector(source.id, gate),
+            _ => return None,
+        },
+    };
+    // nothing is wired into itself
+    Some(link).filter(|link| link.source != link.target)
+}
+
 pub fn propagate_source_to_target(
     mut ev_sourcestate: EventReader<SourceStateChanged>,
     links: Query<&ControlLink>,
-    mut targets: Query<&mut ControlTarget>,
+    sources: Query<&ControlSource>,
+    mut targets: Query<(&mut ControlTarget, Option<&Gate>)>,
 ) {
     for event in ev_sourcestate.read() {
         for link in links.iter() {
             if link.source == event.source_id {
-                let mut target = targets
+                let (mut target, gate) = targets
                     .iter_mut()
-                    .find(|target| target.id == link.target)
+                    .find(|(target, _)| target.id == link.target)
                     .unwrap();
 
-                if event.on && !target.activated {
-                    target.activated = true;
-                } else if !event.on && target.activated {
-                    target.activated = false;
+                // gates work their output out from everything wired into them
+                let on = match gate {
+                    Some(gate) => gate_output(gate.kind, target.id, links.iter(), sources.iter()),
+                    None => event.on,
+                };
+                if on != target.activated {
+                    target.activated = on;
                 }
             }
         }
     }
 }
 
+/// Whether each source wired into a target is on, in order of source id. Multiplexer selectors
+/// aren't counted as inputs
+pub fn link_inputs<'a>(
+    target: u8,
+    links: impl Iterator<Item = &'a ControlLink>,
+    sources: impl Iterator<Item = &'a ControlSource>,
+) -> Vec<bool> {
+    let on = sources.map(|s| (s.id, s.on)).collect::<HashMap<_, _>>();
+    let mut source_ids = links
+        .filter(|l| l.target == target && !l.select)
+        .map(|l| l.source)
+        .collect::<Vec<_>>();
+    source_ids.sort();
+    source_ids.dedup();
+    source_ids
+        .iter()
+        .map(|id| on.get(id).copied().unwrap_or(false))
+        .collect()
+}
+
+/// A gate's output from everything wired into it. A multiplexer's selector is off while nothing
+/// is wired into it
+pub fn gate_output<'a>(
+    kind: GateKind,
+    target: u8,
+    links: impl Iterator<Item = &'a ControlLink>,
+    sources: impl Iterator<Item = &'a ControlSource>,
+) -> bool {
+    let links = links.collect::<Vec<_>>();
+    let sources = sources.collect::<Vec<_>>();
+    let inputs = link_inputs(target, links.iter().copied(), sources.iter().copied());
+    if kind != GateKind::Mux {
+        return kind.output(&inputs);
+    }
+
+    let selector = links
+        .iter()
+        .find(|l| l.target == target && l.select)
+        .and_then(|l| sources.iter().find(|s| s.id == l.source))
+        .is_some_and(|s| s.on);
+    let inputs = std::iter::once(selector).chain(inputs).collect::<Vec<_>>();
+    kind.output(&inputs)
+}
+
 fn link_added(
     trigger: Trigger<OnAdd, ControlLink>,
     links: Query<(&ControlLink, Entity)>,
     mut sources: Query<&mut ControlSource>,
-    mut targets: Query<&mut ControlTarget>,
+    mut targets: Query<(&mut ControlTarget, Option<&Gate>, Has<MultipleInputs>)>,
     mut commands: Commands,
 ) {
     let (link, new_link_entity) = links.get(trigger.entity()).unwrap();
-    let mut new_target = targets.iter_mut().find(|t| t.id == link.target).unwrap();
+    let (mut new_target, gate, multiple_inputs) = targets
+        .iter_mut()
+        .find(|(t, ..)| t.id == link.target)
+        .unwrap();
 
-    // despawn other link if present
+    // despawn other link if present. Targets with multiple inputs keep their other links, and
+    // only drop the same wire made twice. A multiplexer's selector only ever has one link
     for (other_link, other_link_entity) in links.iter() {
-        if other_link.target == new_target.id && other_link_entity != new_link_entity {
+        let replaced = other_link.select == link.select
+            && (link.select || !multiple_inputs || other_link.source == link.source);
+        if other_link.target == new_target.id && other_link_entity != new_link_entity && replaced {
             // despawning the link will trigger `link_removed` and should take care of
             // updating the old source/targets
             debug!(
@@ -250,6 +328,14 @@ fn link_added(
             break;
         }
     }
+    let gate_on = gate.map(|gate| {
+        gate_output(
+            gate.kind,
+            link.target,
+            links.iter().map(|(l, _)| l),
+            sources.iter(),
+        )
+    });
 
     // update new source state
     let mut source = sources.iter_mut().find(|s| s.id == link.source).unwrap();
@@ -257,36 +343,118 @@ fn link_added(
 
     // update new target state
     new_target.connected = true;
-    new_target.activated = source.on; // propagate activation    
+    new_target.activated = gate_on.unwrap_or(source.on); // propagate activation
 }
 
 fn link_removed(
-    trigger: Trigger<OnAdd, ControlLink>,
-    links: Query<&ControlLink>,
+    trigger: Trigger<OnRemove, ControlLink>,
+    links: Query<(&ControlLink, Entity)>,
     mut sources: Query<&mut ControlSource>,
-    mut targets: Query<&mut ControlTarget>,
+    mut targets: Query<(&mut ControlTarget, Option<&Gate>)>,
 ) {
-    let old_link = links.get(trigger.entity()).unwrap();
+    let (old_link, _) = links.get(trigger.entity()).unwrap();
+    // the link is still around while it's being removed, so leave it out of what remains
+    let remaining_links = links
+        .iter()
+        .filter(|(_, entity)| *entity != trigger.entity())
+        .map(|(link, _)| link)
+        .collect::<Vec<_>>();
 
     // it could be the case that a prior connection was severed but one still exists
     // make sure there are no more remaining links for this target before marking it as disconnected
+    let source_connected = remaining_links.iter().any(|l| l.source == old_link.source);
     if let Some(mut old_source) = sources.iter_mut().find(|s| s.id == old_link.source) {
-        let remaining_links = links
-            .iter()
-            .filter(|l| l.source == old_source.id)
-            .collect::<Vec<_>>();
-        if remaining_links.len() == 0 {
-            old_source.connected = false;
-        }
+        old_source.connected &= source_connected;
     }
 
-    if let Some(mut old_target) = targets.iter_mut().find(|t| t.id == old_link.target) {
-        let remaining_links = links
-            .iter()
-            .filter(|l| l.target == old_target.id)
-            .collect::<Vec<_>>();
-        if remaining_links.len() == 0 {
-            old_target.connected = false;
+    let target_connected = remaining_links.iter().any(|l| l.target == old_link.target);
+    if let Some((mut old_target, gate)) = targets.iter_mut().find(|(t, _)| t.id == old_link.target)
+    {
+        old_target.connected &= target_connected;
+        // gates work their output out again from the inputs they have left
+        if let Some(gate) = gate {
+            old_target.activated = gate_output(
+                gate.kind,
+                old_target.id,
+                remaining_links.iter().copied(),
+                sources.iter(),
+            );
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connection_ends() {
+        let (start, end) = (Entity::from_raw(1), Entity::from_raw(2));
+        let panel = (
+            Some(ControlSource::new(2, false, false)),
+            Some(ControlTarget::new(2, false, false)),
+        );
+        let from_panel = ConnectionState {
+            source_entity: Some(start),
+            source_id: Some(2),
+            target_entity: Some(start),
+            target_id: Some(2),
+            ..default()
+        };
+
+        // a panel or gate's output is wired into the next panel or gate
+        let gate = ControlTarget::new(7, false, false);
+        let link = connection_link(&from_panel, end, None, Some(&gate), None).unwrap();
+        assert_eq!((link.source, link.target, link.select), (2, 7, false));
+
+        // a switch has no input, so it's wired into the panel instead
+        let switch = ControlSource::new(3, false, false);
+        let link = connection_link(&from_panel, end, Some(&switch), None, None).unwrap();
+        assert_eq!((link.source, link.target), (3, 2));
+
+        // the selector of a multiplexer gets its own link
+        let selector = MuxSelector { gate: 7 };
+        let link = connection_link(&from_panel, end, None, None, Some(&selector)).unwrap();
+        assert_eq!((link.source, link.target, link.select), (2, 7, true));
+
+        // nothing is wired into itself
+        let (source, target) = panel;
+        assert!(
+            connection_link(&from_panel, start, source.as_ref(), target.as_ref(), None).is_none()
+        );
+    }
+
+    #[test]
+    fn unwiring_gates() {
+        let mut world = World::new();
+        world.add_observer(link_added);
+        world.add_observer(link_removed);
+        world.spawn(ControlSource::new(3, true, false));
+        world.spawn(ControlSource::new(4, false, false));
+        let gate = world
+            .spawn((
+                Gate { kind: GateKind::Or },
+                MultipleInputs,
+                ControlTarget::new(1, false, false),
+            ))
+            .id();
+        let on_link = world.spawn(ControlLink::new(3, 1)).id();
+        let off_link = world.spawn(ControlLink::new(4, 1)).id();
+        world.flush();
+        let target = world.get::<ControlTarget>(gate).unwrap();
+        assert!(target.activated && target.connected);
+
+        // the gate is left with just the source that's off
+        world.despawn(on_link);
+        world.flush();
+        let target = world.get::<ControlTarget>(gate).unwrap();
+        assert!(!target.activated && target.connected);
+        let mut sources = world.query::<&ControlSource>();
+        assert!(!sources.iter(&world).find(|s| s.id == 3).unwrap().connected);
+
+        world.despawn(off_link);
+        world.flush();
+        let target = world.get::<ControlTarget>(gate).unwrap();
+        assert!(!target.activated && !target.connected);
+    }
+}
@@ -59,10 +59,16 @@ impl ControlTarget {
     }
 }
 
+/// A target that keeps every link wired into it, instead of only the newest one
+#[derive(Component, Debug, Default)]
+pub struct MultipleInputs;
+
 #[derive(Component)]
 pub struct ControlLink {
     pub target: u8,
     pub source: u8,
+    /// Wired into the selector of a multiplexer gate instead of its inputs
+    pub select: bool,
 }
 
 impl ControlLink {
@@ -70,6 +76,15 @@ impl ControlLink {
         Self {
             target: target_id,
             source: source_id,
+            select: false,
+        }
+    }
+
+    /// A link into the selector of the multiplexer gate `target_id`
+    pub fn selector(source_id: u8, target_id: u8) -> Self {
+        Self {
+            select: true,
+            ..Self::new(source_id, target_id)
         }
     }
 }
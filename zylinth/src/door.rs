@@ -1,22 +1,143 @@
-use avian2d::prelude::{Collider, RigidBody};
+use avian2d::prelude::{Collider, ColliderDisabled, RigidBody};
 use bevy::prelude::*;
 
-use crate::defs::{ControlLink, ControlSource, ControlTarget};
-use crate::map::{Tile, TileRole};
+use crate::connections::link_inputs;
+use crate::defs::{ControlLink, ControlSource, ControlTarget, MultipleInputs};
+use crate::keycard::Keycards;
+use crate::map::{DoorKind, GateKind, Tile, TileRole};
+use crate::player::Player;
 
-#[derive(Component)]
-pub struct Door;
+/// How long a door takes to open or close, in seconds
+const DOOR_SECONDS: f32 = 0.4;
+
+/// How close the player has to be to the middle of a door to keep it from closing
+const DOORWAY_MARGIN: f32 = 12.0;
+
+/// How many tiles away keycard and one way doors notice the player coming
+const DOOR_SENSE_TILES: f32 = 1.5;
+
+#[derive(Component, Debug)]
+pub struct Door {
+    /// Whether the door is open while nothing powers it
+    pub starts_open: bool,
+    pub kind: DoorKind,
+    pub state: DoorState,
+    /// How far open the door is, from 0 to 1
+    progress: f32,
+    /// How far away keycard and one way doors notice the player coming
+    reach: f32,
+    /// Seconds a timed door has been open since it was last powered
+    open_for: f32,
+    was_powered: bool,
+    /// The panel that slides up when the door opens
+    panel: Entity,
+    collider: Entity,
+}
+
+/// Where the player is, from the middle of the door
+#[derive(Debug, Clone, Copy, Default)]
+struct DoorVisitor {
+    offset: Vec2,
+    has_key: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DoorState {
+    Closed,
+    Opening,
+    Open,
+    Closing,
+}
 
 pub struct DoorPlugin;
 
 impl Plugin for DoorPlugin {
     fn build(&self, app: &mut App) {
         app.add_observer(init_door);
-        app.add_systems(Update, door_target_state_changed);
+        app.add_systems(Update, update_doors);
     }
 }
 
-/// A system to render the door as "closed"
+impl Door {
+    fn new(starts_open: bool, kind: DoorKind, size: f32, panel: Entity, collider: Entity) -> Self {
+        Self {
+            starts_open,
+            kind,
+            state: DoorState::Closed,
+            progress: 0.0,
+            reach: size * DOOR_SENSE_TILES,
+            open_for: 0.0,
+            was_powered: false,
+            panel,
+            collider,
+        }
+    }
+
+    /// Whether the door should be open, given whether it's powered and where the player is
+    fn wants_open(&mut self, powered: bool, player: Option<DoorVisitor>, delta: f32) -> bool {
+        let open = powered != self.starts_open;
+        let reach = self.reach;
+        match self.kind {
+            DoorKind::Standard | DoorKind::AllLinks => open,
+            DoorKind::Timed { seconds } => {
+                if powered != self.was_powered {
+                    self.open_for = 0.0;
+                }
+                self.was_powered = powered;
+                if open {
+                    self.open_for += delta;
+                }
+                open && self.open_for < seconds as f32
+            }
+            // only from behind, heading through it
+            DoorKind::OneWay(heading) => {
+                open && player.is_some_and(|p| {
+                    p.offset.length() < reach && p.offset.dot(heading.world()) <= 0.0
+                })
+            }
+            DoorKind::Keycard => player.is_some_and(|p| p.has_key && p.offset.length() < reach),
+        }
+    }
+
+    /// Move the door towards open or closed. A closing door waits while something is in the way
+    fn step(&mut self, open: bool, blocked: bool, delta: f32) {
+        self.state = match (self.state, open) {
+            (DoorState::Closed | DoorState::Closing, true) => DoorState::Opening,
+            (DoorState::Open | DoorState::Opening, false) => DoorState::Closing,
+            (state, _) => state,
+        };
+
+        match self.state {
+            DoorState::Opening => {
+                self.progress = (self.progress + delta / DOOR_SECONDS).min(1.0);
+                if self.progress >= 1.0 {
+                    self.state = DoorState::Open;
+                }
+            }
+            DoorState::Closing if !blocked => {
+                self.progress = (self.progress - delta / DOOR_SECONDS).max(0.0);
+                if self.progress <= 0.0 {
+                    self.state = DoorState::Closed;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Colour of the door's panel, so each kind can be told apart before walking up to it
+fn panel_color(kind: DoorKind) -> Color {
+    match kind {
+        DoorKind::Standard => Color::srgb(1.0, 0.0, 0.0),
+        DoorKind::Timed { .. } => Color::srgb(1.0, 0.5, 0.0),
+        DoorKind::OneWay(_) => Color::srgb(0.0, 0.4, 1.0),
+        // the same yellow as the keycard's light
+        DoorKind::Keycard => Color::srgb_u8(255, 210, 60),
+        DoorKind::AllLinks => Color::srgb(0.6, 0.0, 0.8),
+    }
+}
+
+/// Give door tiles their panel and collider, starting open or closed depending on what powers them
 pub fn init_door(
     trigger: Trigger<OnAdd, Tile>,
     tiles: Query<(&Tile, Entity)>,
@@ -27,85 +148,200 @@ pub fn init_door(
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
     let (tile, entity) = tiles.get(trigger.entity()).unwrap();
-    if let Some(TileRole::Door(id)) = tile.role {
-        let existing_links = links.iter().filter(|l| l.target == id).collect::<Vec<_>>();
-        let connected = existing_links.len() > 0;
-        let mut on = false;
-        if connected {
-            let source = sources
-                .iter()
-                .find(|s| s.id == existing_links[0].source)
-                .unwrap();
-            on = source.on;
-        }
-        commands
-            .entity(entity)
-            .insert((Door, ControlTarget::new(id, on, connected)));
-        close_door(entity, tile, &mut commands, &mut meshes, &mut materials);
+    let Some(TileRole::Door(id, starts_open, kind)) = tile.role else {
+        return;
+    };
+    let existing_links = links.iter().filter(|l| l.target == id).collect::<Vec<_>>();
+    let connected = !existing_links.is_empty();
+    let mut on = false;
+    if connected {
+        let source = sources
+            .iter()
+            .find(|s| s.id == existing_links[0].source)
+            .unwrap();
+        on = source.on;
+    }
+
+    let square = meshes.add(Rectangle::new(tile.width as f32, tile.height as f32));
+    let panel = commands
+        .spawn((
+            Mesh2d(square),
+            MeshMaterial2d(materials.add(panel_color(kind))),
+        ))
+        .id();
+    let collider = commands
+        .spawn((
+            RigidBody::Static,
+            Collider::rectangle(tile.width as f32, tile.height as f32),
+            Transform::from_xyz(0., 0., 0.1),
+        ))
+        .id();
+    commands.entity(entity).add_children(&[panel, collider]);
+
+    let mut door = Door::new(starts_open, kind, tile.width as f32, panel, collider);
+    if door.wants_open(on, None, 0.0) {
+        door.state = DoorState::Open;
+        door.progress = 1.0;
+        commands.entity(collider).insert(ColliderDisabled);
+    }
+    commands
+        .entity(entity)
+        .insert((door, ControlTarget::new(id, on, connected)));
+    if kind == DoorKind::AllLinks {
+        commands.entity(entity).insert(MultipleInputs);
     }
 }
 
-pub fn door_target_state_changed(
-    changed_doors: Query<(&mut ControlTarget, &Tile, Entity), (With<Door>, Changed<ControlTarget>)>,
-    colliders: Query<(Entity, &Parent), With<Collider>>,
-    mut mesh_query: Query<(&mut Transform, &Parent), With<Mesh2d>>,
+/// Open and close doors to match their targets, sliding the panel and only blocking the way when
+/// fully closed
+pub fn update_doors(
+    time: Res<Time>,
+    mut doors: Query<(&mut Door, &ControlTarget, &Tile, &GlobalTransform)>,
+    mut panels: Query<&mut Transform, With<Mesh2d>>,
+    player: Query<(&GlobalTransform, &Keycards), With<Player>>,
+    links: Query<&ControlLink>,
+    sources: Query<&ControlSource>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    for (target, tile, entity) in changed_doors.iter() {
-        if target.activated {
-            debug!("Opening door {}", target.id);
-            open_door(entity, &colliders, &mut mesh_query, &mut commands);
-        } else {
-            close_door(entity, tile, &mut commands, &mut meshes, &mut materials);
+    let player = player.get_single().ok();
+    for (mut door, target, tile, transform) in doors.iter_mut() {
+        let powered = match door.kind {
+            DoorKind::AllLinks => {
+                GateKind::And.output(&link_inputs(target.id, links.iter(), sources.iter()))
+            }
+            _ => target.activated,
+        };
+        let center = transform.translation().truncate();
+        let visitor = player.map(|(player, keycards)| DoorVisitor {
+            offset: player.translation().truncate() - center,
+            has_key: keycards.has(target.id),
+        });
+        let open = door.wants_open(powered, visitor, time.delta_secs());
+        let at_rest = matches!(door.state, DoorState::Open | DoorState::Closed);
+        if at_rest && open == (door.state == DoorState::Open) {
+            continue;
         }
-    }
-}
 
-fn open_door(
-    door_entity: Entity,
-    colliders: &Query<(Entity, &Parent), With<Collider>>,
-    meshes: &mut Query<(&mut Transform, &Parent), With<Mesh2d>>,
-    commands: &mut Commands,
-) {
-    // TODO: nice exit animation where it slides down or something
-    for (mut transform, parent) in meshes.iter_mut() {
-        if parent.get() == door_entity {
-            transform.scale = Vec3::new(1.0, 0.0, 1.0);
+        let half_size = Vec2::new(tile.width as f32, tile.height as f32) / 2.0;
+        let blocked = visitor.is_some_and(|visitor| {
+            let offset = visitor.offset.abs();
+            offset.cmplt(half_size + DOORWAY_MARGIN).all()
+        });
+        let before = door.state;
+        door.step(open, blocked, time.delta_secs());
+        if before != door.state {
+            debug!("Door {} is {:?}", target.id, door.state);
         }
-    }
 
-    // remove collider
-    for (entity, parent) in colliders.iter() {
-        if parent.get() == door_entity {
-            commands.entity(entity).despawn();
+        if before == DoorState::Closed && door.state != DoorState::Closed {
+            commands.entity(door.collider).insert(ColliderDisabled);
+        } else if door.state == DoorState::Closed && before != DoorState::Closed {
+            commands.entity(door.collider).remove::<ColliderDisabled>();
+        }
+
+        // slide up into the top of the frame
+        if let Ok(mut panel) = panels.get_mut(door.panel) {
+            panel.scale.y = 1.0 - door.progress;
+            panel.translation.y = door.progress * half_size.y;
         }
     }
 }
 
-fn close_door(
-    door_entity: Entity,
-    door_tile: &Tile,
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-) {
-    // "closed" mesh
-    let square = meshes.add(Rectangle::new(32., 32.));
-    let color = Color::srgb(1.0, 0.0, 0.0);
-    let mesh = commands
-        .spawn((Mesh2d(square), MeshMaterial2d(materials.add(color))))
-        .id();
-    commands.entity(door_entity).add_child(mesh);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Heading;
 
-    // add collider
-    let collider = commands
-        .spawn((
-            RigidBody::Static,
-            Collider::rectangle(door_tile.width as f32, door_tile.height as f32),
-            Transform::from_xyz(0., 0., 0.1),
-        ))
-        .id();
-    commands.entity(door_entity).add_child(collider);
+    fn door(starts_open: bool, kind: DoorKind) -> Door {
+        Door::new(
+            starts_open,
+            kind,
+            32.0,
+            Entity::PLACEHOLDER,
+            Entity::PLACEHOLDER,
+        )
+    }
+
+    fn assert_door(door: &Door, state: DoorState, progress: f32) {
+        assert_eq!(door.state, state);
+        assert!((door.progress - progress).abs() < 1e-4, "{}", door.progress);
+    }
+
+    #[test]
+    fn open_and_close() {
+        let mut door = door(false, DoorKind::Standard);
+        assert!(door.wants_open(true, None, 0.0));
+        door.step(true, false, DOOR_SECONDS / 2.0);
+        assert_eq!(door.state, DoorState::Opening);
+        door.step(true, false, DOOR_SECONDS);
+        assert_door(&door, DoorState::Open, 1.0);
+
+        // changing its mind half way turns it around from where it got to
+        door.step(false, false, DOOR_SECONDS / 4.0);
+        assert_door(&door, DoorState::Closing, 0.75);
+        door.step(true, false, DOOR_SECONDS / 4.0);
+        assert_door(&door, DoorState::Open, 1.0);
+
+        // someone standing in the doorway holds it open
+        door.step(false, true, DOOR_SECONDS * 2.0);
+        assert_door(&door, DoorState::Closing, 1.0);
+        door.step(false, false, DOOR_SECONDS * 2.0);
+        assert_door(&door, DoorState::Closed, 0.0);
+    }
+
+    #[test]
+    fn starts_open() {
+        let mut door = door(true, DoorKind::Standard);
+        assert!(door.wants_open(false, None, 0.0));
+        assert!(!door.wants_open(true, None, 0.0));
+    }
+
+    #[test]
+    fn door_kinds() {
+        let mut timed = door(false, DoorKind::Timed { seconds: 2 });
+        assert!(timed.wants_open(true, None, 1.0));
+        assert!(!timed.wants_open(true, None, 1.5));
+        // switching it off and on again starts the timer over
+        assert!(!timed.wants_open(false, None, 0.1));
+        assert!(timed.wants_open(true, None, 0.1));
+
+        let visitor = |x: f32, has_key: bool| {
+            Some(DoorVisitor {
+                offset: Vec2::new(x, 0.0),
+                has_key,
+            })
+        };
+        let mut one_way = door(false, DoorKind::OneWay(Heading::Right));
+        assert!(one_way.wants_open(true, visitor(-20.0, false), 0.0));
+        assert!(!one_way.wants_open(true, visitor(20.0, false), 0.0));
+        assert!(!one_way.wants_open(true, visitor(-100.0, false), 0.0));
+        assert!(!one_way.wants_open(false, visitor(-20.0, false), 0.0));
+
+        // keycard doors don't need power
+        let mut keycard = door(false, DoorKind::Keycard);
+        assert!(keycard.wants_open(false, visitor(20.0, true), 0.0));
+        assert!(!keycard.wants_open(true, visitor(20.0, false), 0.0));
+        assert!(!keycard.wants_open(false, visitor(100.0, true), 0.0));
+    }
+
+    #[test]
+    fn panel_colors() {
+        let kinds = [
+            DoorKind::Standard,
+            DoorKind::Timed { seconds: 4 },
+            DoorKind::OneWay(Heading::Up),
+            DoorKind::Keycard,
+            DoorKind::AllLinks,
+        ];
+        for (i, a) in kinds.iter().enumerate() {
+            for b in &kinds[i + 1..] {
+                assert_ne!(panel_color(*a), panel_color(*b), "{a:?} and {b:?}");
+            }
+        }
+        // one way doors look the same whichever way they go
+        assert_eq!(
+            panel_color(DoorKind::OneWay(Heading::Up)),
+            panel_color(DoorKind::OneWay(Heading::Left))
+        );
+    }
 }
@@ -0,0 +1,237 @@
+use avian2d::prelude::{Collider, CollisionLayers, RigidBody, Sensor};
+use bevy::prelude::*;
+
+use crate::connections::{SourceStateChanged, gate_output};
+use crate::defs::{ControlLink, ControlSource, ControlTarget, GameLayer, MultipleInputs};
+use crate::map::{GateKind, Tile, TileRole, Tileset, TuesdayTile};
+use crate::selection::Selectable;
+
+/// How much a gate's sprite is dimmed while its output is off
+const OFF_TINT: f32 = 0.5;
+/// How far below a multiplexer its selector is
+const SELECTOR_OFFSET: f32 = 12.0;
+const SELECTOR_RADIUS: f32 = 3.0;
+
+type ChangedGates = (With<Gate>, Changed<ControlTarget>);
+
+/// A logic gate. Its target is activated while its output is on, which it then passes along as a
+/// source
+#[derive(Component, Debug)]
+pub struct Gate {
+    pub kind: GateKind,
+}
+
+/// Where the selector of a multiplexer gate is wired into, separately from its inputs
+#[derive(Component, Debug)]
+pub struct MuxSelector {
+    pub gate: u8,
+}
+
+pub struct GatePlugin;
+
+impl Plugin for GatePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_observer(gate_added);
+        app.add_systems(Update, (target_changed, selection_changed, draw_selectors));
+    }
+}
+
+fn gate_added(
+    trigger: Trigger<OnAdd, Tile>,
+    tiles: Query<(Entity, &Tile)>,
+    links: Query<&ControlLink>,
+    sources: Query<&ControlSource>,
+    mut commands: Commands,
+) {
+    let (entity, tile) = tiles.get(trigger.entity()).unwrap();
+    let Some(TileRole::Gate(id, kind)) = tile.role else {
+        return;
+    };
+    let on = gate_output(kind, id, links.iter(), sources.iter());
+    let source_connected = links.iter().any(|l| l.source == id);
+    let target_connected = links.iter().any(|l| l.target == id);
+
+    // gates are both sources and targets, like panels, but keep every link wired into them
+    commands.entity(entity).insert((
+        Gate { kind },
+        MultipleInputs,
+        ControlSource::new(id, on, source_connected),
+        ControlTarget::new(id, on, target_connected),
+        Selectable::default(),
+        RigidBody::Static,
+        Sensor,
+        Collider::rectangle(14.0, 20.0),
+        CollisionLayers::new(GameLayer::Interactables, [GameLayer::Player]),
+    ));
+
+    if kind == GateKind::Mux {
+        commands.entity(entity).with_children(|parent| {
+            parent.spawn((
+                MuxSelector { gate: id },
+                Selectable::default(),
+                Transform::from_xyz(0.0, -SELECTOR_OFFSET, 0.0),
+                Sensor,
+                Collider::circle(SELECTOR_RADIUS),
+                CollisionLayers::new(GameLayer::Interactables, [GameLayer::Player]),
+            ));
+        });
+    }
+}
+
+/// Pass a gate's new output along to whatever it's wired into
+fn target_changed(
+    mut gates: Query<(&Gate, &ControlTarget, &mut ControlSource, &mut Sprite), ChangedGates>,
+    mut ev_sourcestate: EventWriter<SourceStateChanged>,
+) {
+    for (gate, target, mut source, mut sprite) in gates.iter_mut() {
+        if target.activated != source.on {
+            debug!("gate {} changed to {}", target.id, target.activated);
+            source.on = target.activated;
+            ev_sourcestate.send(SourceStateChanged {
+                source_id: source.id,
+                on: target.activated,
+            });
+        }
+
+        sprite.color = gate_tint(gate.kind, source.on);
+    }
+}
+
+/// Colour a gate's sprite is tinted, so each kind can be told apart, dimmed while its output is off
+fn gate_tint(kind: GateKind, on: bool) -> Color {
+    let (r, g, b) = match kind {
+        GateKind::And => (1.0, 0.4, 0.4),
+        GateKind::Or => (0.4, 1.0, 0.4),
+        GateKind::Xor => (0.4, 0.6, 1.0),
+        GateKind::Not => (1.0, 0.4, 1.0),
+        GateKind::Mux => (1.0, 0.9, 0.4),
+    };
+    let dim = if on { 1.0 } else { OFF_TINT };
+    Color::srgb(r * dim, g * dim, b * dim)
+}
+
+fn selection_changed(
+    mut gates: Query<(&Tile, &Gate, &ControlTarget, &Selectable, &mut Sprite), Changed<Selectable>>,
+    tilesets: Res<Assets<Tileset>>,
+) {
+    for (tile, gate, target, selectable, mut sprite) in gates.iter_mut() {
+        let sprite_tile = if selectable.selected {
+            TuesdayTile::PowerSelected
+        } else {
+            TuesdayTile::from_gate(target.id, gate.kind)
+        };
+        let index = tilesets
+            .get(&tile.tileset)
+            .and_then(|tileset| tileset.index(&sprite_tile));
+        if let (Some(atlas), Some(index)) = (&mut sprite.texture_atlas, index) {
+            atlas.index = index;
+        }
+    }
+}
+
+/// Mark where each multiplexer's selector can be wired into
+fn draw_selectors(
+    selectors: Query<(&GlobalTransform, &Selectable), With<MuxSelector>>,
+    mut gizmos: Gizmos,
+) {
+    for (transform, selectable) in selectors.iter() {
+        let color = if selectable.selected {
+            Color::WHITE
+        } else {
+            Color::srgb(OFF_TINT, OFF_TINT, OFF_TINT)
+        };
+        gizmos.circle_2d(transform.translation().truncate(), SELECTOR_RADIUS, color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connections::link_inputs;
+
+    #[test]
+    fn gate_outputs() {
+        let cases = [
+            (GateKind::And, [true, true], true),
+            (GateKind::And, [true, false], false),
+            (GateKind::Or, [false, true], true),
+            (GateKind::Or, [false, false], false),
+            (GateKind::Xor, [true, true], false),
+            (GateKind::Xor, [true, false], true),
+            (GateKind::Not, [false, false], true),
+            (GateKind::Not, [false, true], false),
+        ];
+        for (kind, inputs, on) in cases {
+            assert_eq!(kind.output(&inputs), on, "{kind:?} {inputs:?}");
+        }
+
+        // nothing wired in
+        assert!(!GateKind::And.output(&[]));
+        assert!(GateKind::Not.output(&[]));
+
+        // the selector comes first and picks between the other two
+        assert!(GateKind::Mux.output(&[false, true, false]));
+        assert!(!GateKind::Mux.output(&[true, true, false]));
+        assert!(GateKind::Mux.output(&[true, false, true]));
+        assert!(!GateKind::Mux.output(&[true, true]));
+    }
+
+    #[test]
+    fn gate_tints() {
+        let kinds = [
+            GateKind::And,
+            GateKind::Or,
+            GateKind::Xor,
+            GateKind::Not,
+            GateKind::Mux,
+        ];
+        for (i, a) in kinds.iter().enumerate() {
+            for b in &kinds[i + 1..] {
+                assert_ne!(gate_tint(*a, true), gate_tint(*b, true), "{a:?} and {b:?}");
+            }
+            assert_ne!(gate_tint(*a, true), gate_tint(*a, false), "{a:?}");
+        }
+    }
+
+    #[test]
+    fn inputs_in_source_order() {
+        let links = [
+            ControlLink::new(5, 1),
+            ControlLink::new(3, 1),
+            ControlLink::new(4, 2),
+            // the same wire made twice only counts once
+            ControlLink::new(3, 1),
+        ];
+        let sources = [
+            ControlSource::new(3, true, true),
+            ControlSource::new(4, false, true),
+            ControlSource::new(5, false, true),
+        ];
+        assert_eq!(
+            link_inputs(1, links.iter(), sources.iter()),
+            vec![true, false]
+        );
+        assert_eq!(link_inputs(2, links.iter(), sources.iter()), vec![false]);
+        assert!(link_inputs(3, links.iter(), sources.iter()).is_empty());
+    }
+
+    #[test]
+    fn mux_selector() {
+        let sources = [
+            ControlSource::new(3, false, true),
+            ControlSource::new(4, true, true),
+            ControlSource::new(5, true, true),
+        ];
+        // nothing is wired into the selector, so the first input is picked
+        let mut links = vec![ControlLink::new(3, 1), ControlLink::new(4, 1)];
+        assert!(!gate_output(GateKind::Mux, 1, links.iter(), sources.iter()));
+
+        // the selector isn't an input itself
+        links.push(ControlLink::selector(5, 1));
+        assert_eq!(
+            link_inputs(1, links.iter(), sources.iter()),
+            vec![false, true]
+        );
+        assert!(gate_output(GateKind::Mux, 1, links.iter(), sources.iter()));
+    }
+}
@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+
+use crate::map::{NewMap, PatchTilemap, Tile, TileRole, TuesdayTile};
+use crate::player::Player;
+
+/// Keycards the player is carrying, by the id of the door they open
+#[derive(Component, Debug, Default)]
+pub struct Keycards(HashSet<u8>);
+
+impl Keycards {
+    pub fn has(&self, id: u8) -> bool {
+        self.0.contains(&id)
+    }
+}
+
+pub struct KeycardPlugin;
+
+impl Plugin for KeycardPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (pick_up_keycards, drop_keycards));
+    }
+}
+
+/// Walking over a keycard takes it off the map and into the player's pocket
+fn pick_up_keycards(
+    mut player: Query<(&GlobalTransform, &mut Keycards), With<Player>>,
+    tiles: Query<(&Tile, &GlobalTransform, &Parent)>,
+    mut commands: Commands,
+) {
+    let Ok((player, mut keycards)) = player.get_single_mut() else {
+        return;
+    };
+    let position = player.translation().truncate();
+    for (tile, transform, tilemap) in tiles.iter() {
+        let Some(TileRole::Keycard(id)) = tile.role else {
+            continue;
+        };
+        if transform.translation().truncate().distance(position) < tile.width as f32 / 2.0 {
+            debug!("Picked up keycard {id}");
+            keycards.0.insert(id);
+            commands.queue(PatchTilemap::<TuesdayTile> {
+                tilemap: tilemap.get(),
+                tiles: vec![(tile.grid_x as usize, tile.grid_y as usize, None)],
+            });
+        }
+    }
+}
+
+/// Keycards only open doors on the map they were found on
+fn drop_keycards(mut ev_newmap: EventReader<NewMap>, mut player: Query<&mut Keycards>) {
+    for _ in ev_newmap.read() {
+        for mut keycards in player.iter_mut() {
+            keycards.0.clear();
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use crate::defs::ControlLink;
+use crate::map::{
+    BuildingLayout, MazeAlgorithm, PuzzleSpec, SpawnBuildingMap, SpawnLevelFromAsset, Tile,
+    TileRole, Tilemap, TuesdayTile, tilesets_ready,
+};
+use crate::player::Player;
+use crate::seed::RngSeed;
+
+/// Levels made by hand, which are played before any generated ones
+const HANDCRAFTED_LEVELS: &[&str] = &["levels/starter_room.level.ron"];
+
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelComplete>();
+        app.insert_resource(CurrentLevel(0));
+
+        app.add_systems(Update, spawn_first_level.run_if(tilesets_ready));
+        app.add_systems(Update, (reach_exit, next_level).chain());
+    }
+}
+
+/// Everything a map is rendered into which isn't part of something bigger
+type MapRoots = (Or<(With<Tilemap>, With<Tile>)>, Without<Parent>);
+
+/// How many levels the player has finished
+#[derive(Resource, Debug)]
+pub struct CurrentLevel(pub u32);
+
+/// The player made it to the exit of the current level
+#[derive(Event)]
+pub struct LevelComplete {
+    pub level: u32,
+}
+
+/// Generated map for a level, a switch puzzle that gets bigger and harder as the levels go on
+pub fn level_map(level: u32) -> SpawnBuildingMap {
+    // later buildings lead from the labs into the reactor after the first door
+    let mut biomes = vec![TuesdayTile::name().to_string()];
+    if level >= 3 {
+        biomes.push("reactor".to_string());
+    }
+    SpawnBuildingMap {
+        width: 48 + 12 * (level as usize / 2).min(4),
+        height: 30 + 10 * (level as usize / 3).min(2),
+        density: 0.125,
+        branch_factor: 0.25,
+        wander_factor: 0.5,
+        layout: BuildingLayout::SwitchPuzzle {
+            algorithm: MazeAlgorithm::Wilson,
+            spec: PuzzleSpec::for_level(level.min(u8::MAX as u32) as u8),
+        },
+        // a handcrafted room turns up from the second generated level, and another later on
+        puzzle_rooms: (level / 2).min(2) as u8,
+        biomes,
+    }
+}
+
+/// Spawn the handcrafted level with this number if there is one, otherwise generate it
+fn queue_level(level: u32, commands: &mut Commands) {
+    match HANDCRAFTED_LEVELS.get(level as usize) {
+        Some(path) => commands.queue(SpawnLevelFromAsset {
+            path: path.to_string(),
+        }),
+        None => commands.queue(level_map(level)),
+    }
+}
+
+/// Maps can't be drawn until the tilesets have loaded, so this waits for them
+fn spawn_first_level(level: Res<CurrentLevel>, mut spawned: Local<bool>, mut commands: Commands) {
+    if !*spawned {
+        *spawned = true;
+        queue_level(level.0, &mut commands);
+    }
+}
+
+fn reach_exit(
+    player: Query<&GlobalTransform, With<Player>>,
+    tiles: Query<(&Tile, &GlobalTransform)>,
+    level: Res<CurrentLevel>,
+    mut ev_complete: EventWriter<LevelComplete>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let position = player.translation().truncate();
+    let on_exit = tiles.iter().any(|(tile, transform)| {
+        tile.role == Some(TileRole::Exit)
+            && transform.translation().truncate().distance(position) < tile.width as f32 / 2.0
+    });
+    if on_exit {
+        ev_complete.send(LevelComplete { level: level.0 });
+    }
+}
+
+/// Tear down the current map and spawn the next one. The player is kept and moved to the new
+/// start once the map is ready
+fn next_level(
+    mut ev_complete: EventReader<LevelComplete>,
+    maps: Query<Entity, MapRoots>,
+    links: Query<Entity, With<ControlLink>>,
+    mut level: ResMut<CurrentLevel>,
+    mut seed: ResMut<RngSeed>,
+    mut commands: Commands,
+) {
+    // the exit can be reached more than once before the map is torn down, only move on once
+    let Some(complete) = ev_complete.read().last() else {
+        return;
+    };
+    info!("Finished level {}", complete.level);
+
+    // tiles, colliders and door meshes are all children of the tilemaps
+    for entity in maps.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    for entity in links.iter() {
+        commands.entity(entity).despawn();
+    }
+
+    level.0 += 1;
+    seed.0 = ChaCha8Rng::seed_from_u64(seed.0).random();
+    debug!("Using rng seed {} for level {}", seed.0, level.0);
+    queue_level(level.0, &mut commands);
+}
@@ -6,7 +6,10 @@ use bevy_lit::prelude::Lighting2dPlugin;
 use camera::CameraSetup;
 use connections::ConnectionsPlugin;
 use door::DoorPlugin;
-use map::{SpawnBuildingMap, TileLayoutPlugin};
+use gate::GatePlugin;
+use keycard::KeycardPlugin;
+use level::LevelPlugin;
+use map::TileLayoutPlugin;
 use panel::DoorPanelPlugin;
 use player::PlayerPlugin;
 use seed::SeedPlugin;
@@ -18,6 +21,9 @@ mod camera;
 mod connections;
 mod defs;
 mod door;
+mod gate;
+mod keycard;
+mod level;
 mod map;
 mod panel;
 mod player;
@@ -58,22 +64,14 @@ fn main() {
             TileLayoutPlugin,
             ConnectionsPlugin,
             DoorPlugin,
+            KeycardPlugin,
+            GatePlugin,
             SelectionPlugion,
             SwitchPlugin,
             DoorPanelPlugin,
+            LevelPlugin,
         ))
         .insert_resource(Gravity::ZERO)
         .insert_resource(ClearColor(BASE_COLOR))
-        .add_systems(Startup, startup)
         .run();
 }
-
-fn startup(mut commands: Commands) {
-    commands.queue(SpawnBuildingMap {
-        width: 50,
-        height: 22,
-        density: 0.125,
-        branch_factor: 0.25,
-        wander_factor: 0.5,
-    });
-}
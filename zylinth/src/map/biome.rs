@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+use tilegen::TileGrid;
+
+use super::IsImpassable;
+use super::tuesday::TuesdayTile;
+
+/// How many doors the player has to go through to reach each tile of a wrapped building. Walls
+/// and empty tiles take the depth of the nearest floor, so whole wings can be drawn with one tileset
+pub fn wing_depths(grid: &TileGrid<TuesdayTile>) -> Vec<Vec<usize>> {
+    let width = grid.width();
+    let height = grid.height();
+    let z = grid.depth() - 1;
+    let is_door = |x: usize, y: usize| grid[x][y][z].is_some_and(|t| t.door_id().is_some());
+    let is_floor =
+        |x: usize, y: usize| is_door(x, y) || grid[x][y][z - 1].is_some_and(|t| !t.is_impassable());
+
+    let mut depths: Vec<Vec<Option<usize>>> = vec![vec![None; height]; width];
+    let start = (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .find(|(x, y)| matches!(grid[*x][*y][z], Some(TuesdayTile::PlayerStart(_))))
+        .or_else(|| {
+            (0..width)
+                .flat_map(|x| (0..height).map(move |y| (x, y)))
+                .find(|(x, y)| is_floor(*x, *y))
+        });
+    let Some(start) = start else {
+        return vec![vec![0; height]; width];
+    };
+
+    // walking through a door costs one, everything else is free
+    let mut queue = VecDeque::from([(start, 0)]);
+    while let Some(((x, y), depth)) = queue.pop_front() {
+        if depths[x][y].is_some_and(|d| d <= depth) {
+            continue;
+        }
+        depths[x][y] = Some(depth);
+        for (nx, ny) in neighbours(x, y, width, height) {
+            if !is_floor(nx, ny) || depths[nx][ny].is_some() {
+                continue;
+            }
+            if is_door(nx, ny) {
+                queue.push_back(((nx, ny), depth + 1));
+            } else {
+                queue.push_front(((nx, ny), depth));
+            }
+        }
+    }
+
+    // spread out from the floor over the walls around it
+    let mut queue: VecDeque<(usize, usize)> = (0..width)
+        .flat_map(|x| (0..height).map(move |y| (x, y)))
+        .filter(|(x, y)| depths[*x][*y].is_some())
+        .collect();
+    while let Some((x, y)) = queue.pop_front() {
+        for (nx, ny) in neighbours(x, y, width, height) {
+            if depths[nx][ny].is_none() {
+                depths[nx][ny] = depths[x][y];
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+
+    depths
+        .into_iter()
+        .map(|col| col.into_iter().map(|d| d.unwrap_or(0)).collect())
+        .collect()
+}
+
+fn neighbours(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    [(0, -1), (1, 0), (0, 1), (-1, 0)]
+        .into_iter()
+        .map(move |(dx, dy)| (x as i32 + dx, y as i32 + dy))
+        .filter(move |(nx, ny)| *nx >= 0 && *ny >= 0 && *nx < width as i32 && *ny < height as i32)
+        .map(|(nx, ny)| (nx as usize, ny as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::map::special::starter_room::starter_room;
+    use crate::map::starter::mark_player_start_tile;
+    use crate::map::wall_wrap::wrap_walls;
+
+    #[test]
+    fn starter_room_wings() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut grid = wrap_walls(starter_room().grid, &mut rng);
+        mark_player_start_tile(&mut grid, 1, &mut rng);
+        let depths = wing_depths(&grid);
+
+        let z = grid.depth() - 1;
+        let mut door = None;
+        let mut start = None;
+        for x in 0..grid.width() {
+            for y in 0..grid.height() {
+                match grid[x][y][z] {
+                    Some(TuesdayTile::DoorFrame(_)) => door = Some((x, y)),
+                    Some(TuesdayTile::PlayerStart(_)) => start = Some((x, y)),
+                    _ => {}
+                }
+            }
+        }
+        let (dx, dy) = door.unwrap();
+        let (sx, sy) = start.unwrap();
+        assert_eq!(depths[sx][sy], 0);
+        assert_eq!(depths[dx][dy], 1);
+        // the corridor past the door and the walls around it are the next wing
+        assert!(depths.iter().flatten().any(|d| *d == 1));
+        assert!(depths.iter().flatten().all(|d| *d <= 1));
+        assert_eq!(depths[0][0], depths[1][1]);
+    }
+}
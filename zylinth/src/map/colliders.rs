@@ -0,0 +1,159 @@
+/// A rectangle of tiles covered by one collider, in grid coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ColliderRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl ColliderRect {
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Cover every solid tile with as few rectangles as it can. Each rectangle is grown as wide as
+/// it can go from its top left tile, then as far down as the whole width allows
+pub fn merge_rects(solid: &[Vec<bool>]) -> Vec<ColliderRect> {
+    let width = solid.len();
+    let height = solid.first().map_or(0, |col| col.len());
+    let mut covered = vec![vec![false; height]; width];
+    let free = |covered: &Vec<Vec<bool>>, x: usize, y: usize| solid[x][y] && !covered[x][y];
+
+    let mut rects = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if !free(&covered, x, y) {
+                continue;
+            }
+            let rect_width = (x..width).take_while(|nx| free(&covered, *nx, y)).count();
+            let rect_height = (y..height)
+                .take_while(|ny| (x..x + rect_width).all(|nx| free(&covered, nx, *ny)))
+                .count();
+
+            for col in covered.iter_mut().skip(x).take(rect_width) {
+                col[y..y + rect_height].fill(true);
+            }
+            rects.push(ColliderRect {
+                x,
+                y,
+                width: rect_width,
+                height: rect_height,
+            });
+        }
+    }
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::map::special::starter_room::starter_room;
+    use crate::map::tileset::tuesday_tileset;
+    use crate::map::wall_wrap::wrap_walls;
+
+    fn parse(rows: &str) -> Vec<Vec<bool>> {
+        let rows: Vec<&str> = rows.trim().lines().map(|row| row.trim()).collect();
+        let mut solid = vec![vec![false; rows.len()]; rows[0].len()];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                solid[x][y] = c == '#';
+            }
+        }
+        solid
+    }
+
+    /// Every solid tile is covered by exactly one rectangle and nothing else is covered
+    fn assert_exact_cover(solid: &[Vec<bool>], rects: &[ColliderRect]) {
+        for (x, col) in solid.iter().enumerate() {
+            for (y, is_solid) in col.iter().enumerate() {
+                let covering = rects.iter().filter(|r| r.contains(x, y)).count();
+                assert_eq!(
+                    covering, *is_solid as usize,
+                    "{x},{y} covered {covering} times"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn shapes() {
+        let block = parse(
+            "
+            ###
+            ###
+            ",
+        );
+        assert_eq!(
+            merge_rects(&block),
+            vec![ColliderRect {
+                x: 0,
+                y: 0,
+                width: 3,
+                height: 2
+            }]
+        );
+
+        let corner = parse(
+            "
+            ####
+            #...
+            #...
+            ",
+        );
+        let rects = merge_rects(&corner);
+        assert_eq!(rects.len(), 2);
+        assert_exact_cover(&corner, &rects);
+
+        let ring = parse(
+            "
+            #####
+            #...#
+            #.#.#
+            #...#
+            #####
+            ",
+        );
+        let rects = merge_rects(&ring);
+        assert_eq!(rects.len(), 5);
+        assert_exact_cover(&ring, &rects);
+
+        // single tiles at the edges of the grid
+        let edges = parse(
+            "
+            #..#
+            ....
+            #..#
+            ",
+        );
+        let rects = merge_rects(&edges);
+        assert_eq!(rects.len(), 4);
+        assert_exact_cover(&edges, &rects);
+
+        assert!(merge_rects(&[]).is_empty());
+    }
+
+    #[test]
+    fn starter_room_walls() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let grid = wrap_walls(starter_room().grid, &mut rng);
+        let sprites = tuesday_tileset().layer_to_tile_sprites(&grid, 0, &mut rng);
+        let solid: Vec<Vec<bool>> = sprites
+            .iter()
+            .map(|col| {
+                col.iter()
+                    .map(|s| s.as_ref().is_some_and(|s| s.collider))
+                    .collect()
+            })
+            .collect();
+
+        let rects = merge_rects(&solid);
+        assert_exact_cover(&solid, &rects);
+        let walls = solid.iter().flatten().filter(|s| **s).count();
+        assert!(rects.len() * 3 < walls);
+    }
+}
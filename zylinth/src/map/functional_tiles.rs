@@ -1,8 +1,9 @@
 use bevy::ecs::component::Component;
+use serde::Deserialize;
 
 use super::IsImpassable;
 
-#[derive(Component, Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+#[derive(Component, Copy, Clone, Default, Debug, PartialEq, Eq, Hash, Deserialize)]
 #[allow(dead_code)]
 pub enum UtilityTile {
     #[default]
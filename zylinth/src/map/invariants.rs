@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use lazy_static::lazy_static;
+use tilegen::*;
+
+use super::tuesday::{TuesdayTile, TuesdayTile::*};
+use super::{IsImpassable, WRAPPED_BASE_LAYER, WRAPPED_INTERACTABLES_LAYER};
+
+/// Check a generated building against the invariants, logging anything that breaks them
+pub fn check_invariants(grid: &TileGrid<TuesdayTile>) {
+    let report = lint_building(grid);
+    if !report.is_ok() {
+        warn!("Generated map broke invariants:\n{report}");
+    }
+}
+
+/// Check a generated building against the invariants
+pub fn lint_building(grid: &TileGrid<TuesdayTile>) -> LintReport {
+    grid.lint(&INVARIANTS)
+}
+
+lazy_static! {
+    static ref INVARIANTS: Vec<GridInvariant<TuesdayTile>> = vec![
+        GridInvariant::Tile {
+            name: "door frame has walls on two opposite sides",
+            layer: WRAPPED_INTERACTABLES_LAYER,
+            applies: |ctx| ctx.tile.is_some_and(|t| t.door_id().is_some()),
+            check: |ctx| {
+                (is_wall(ctx.left().below()) && is_wall(ctx.right().below()))
+                    || (is_wall(ctx.up().below()) && is_wall(ctx.down().below()))
+            },
+        },
+        GridInvariant::Tile {
+            name: "floor does not touch empty space",
+            layer: WRAPPED_BASE_LAYER,
+            applies: |ctx| *ctx == Floor,
+            check: |ctx| {
+                ctx.up().tile.is_some()
+                    && ctx.down().tile.is_some()
+                    && ctx.left().tile.is_some()
+                    && ctx.right().tile.is_some()
+            },
+        },
+        GridInvariant::Tile {
+            name: "player start is on floor",
+            layer: WRAPPED_INTERACTABLES_LAYER,
+            applies: |ctx| matches!(ctx.tile, Some(PlayerStart(_))),
+            check: |ctx| ctx.below() == Floor,
+        },
+        GridInvariant::Tile {
+            name: "exit is on floor",
+            layer: WRAPPED_INTERACTABLES_LAYER,
+            applies: |ctx| *ctx == Exit,
+            check: |ctx| ctx.below() == Floor,
+        },
+        GridInvariant::Unique {
+            name: "switch id is unique",
+            layer: WRAPPED_INTERACTABLES_LAYER,
+            key: |t| match t {
+                SwitchLeft(id) | SwitchRight(id) => Some(*id as u32),
+                _ => None,
+            },
+        },
+        GridInvariant::Unique {
+            name: "door id is unique",
+            layer: WRAPPED_INTERACTABLES_LAYER,
+            key: |t| t.door_id().map(|id| id as u32),
+        },
+    ];
+}
+
+fn is_wall(ctx: TileContext<TuesdayTile>) -> bool {
+    ctx.tile.is_some_and(|t| t.is_impassable())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::map::decoration::decorate_empty;
+    use crate::map::special::starter_room::starter_room;
+    use crate::map::starter::mark_player_start_tile;
+    use crate::map::wall_wrap::wrap_walls;
+
+    #[test]
+    fn starter_room_invariants() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut grid = wrap_walls(starter_room().grid, &mut rng);
+        decorate_empty(&mut grid, &mut rng);
+        mark_player_start_tile(&mut grid, 1, &mut rng);
+
+        let report = grid.lint(&INVARIANTS);
+        assert!(report.is_ok(), "{report}");
+    }
+}
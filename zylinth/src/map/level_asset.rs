@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use tilegen::TileGrid;
+
+use super::puzzle::Puzzle;
+use super::spawn_building::spawn_puzzle;
+use super::tuesday::TuesdayTile;
+use crate::defs::ControlLink;
+use crate::seed::RngSeed;
+
+/// Rows are drawn with one character per tile, and spaces are always empty
+const EMPTY: char = ' ';
+
+/// A handcrafted level, stored as a `.level.ron` file
+#[allow(unused)]
+#[derive(Asset, TypePath, Debug, Clone, Deserialize)]
+pub struct LevelAsset {
+    pub name: String,
+    /// Fewest actions the level can be solved in
+    #[serde(default)]
+    pub par: Option<u32>,
+    /// What tile each character in the layers stands for
+    pub legend: HashMap<char, TuesdayTile>,
+    /// Base layer first, then interactables. Each layer is a list of rows from top to bottom
+    pub layers: Vec<Vec<String>>,
+    #[serde(default)]
+    pub links: Vec<LevelLink>,
+    /// Places the player can start, as x,y
+    #[serde(default)]
+    pub starts: Vec<(usize, usize)>,
+    /// Tilesets for each wing of the level, see [`crate::map::SpawnBuildingMap::biomes`]
+    #[serde(default)]
+    pub biomes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct LevelLink {
+    pub source: u8,
+    pub target: u8,
+    /// Wire into the selector of a multiplexer gate instead of its inputs
+    #[serde(default)]
+    pub select: bool,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    NoLayers,
+    /// A character in a layer that isn't in the legend
+    UnknownTile {
+        c: char,
+        x: usize,
+        y: usize,
+        z: usize,
+    },
+    /// A player start outside of the grid
+    StartOutOfBounds {
+        x: usize,
+        y: usize,
+    },
+}
+
+impl Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LevelError::Io(err) => write!(f, "could not read level: {err}"),
+            LevelError::Parse(err) => write!(f, "could not parse level: {err}"),
+            LevelError::NoLayers => write!(f, "level has no layers"),
+            LevelError::UnknownTile { c, x, y, z } => {
+                write!(f, "'{c}' at {x},{y} (layer {z}) is not in the legend")
+            }
+            LevelError::StartOutOfBounds { x, y } => {
+                write!(f, "player start {x},{y} is outside of the level")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+impl From<std::io::Error> for LevelError {
+    fn from(err: std::io::Error) -> Self {
+        LevelError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for LevelError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        LevelError::Parse(err)
+    }
+}
+
+impl LevelAsset {
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, LevelError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+
+    /// Build the puzzle the level describes. Rows shorter than the widest one are padded with empty tiles
+    pub fn to_puzzle(&self) -> Result<Puzzle<TuesdayTile>, LevelError> {
+        if self.layers.is_empty() {
+            return Err(LevelError::NoLayers);
+        }
+        let width = self
+            .layers
+            .iter()
+            .flatten()
+            .map(|row| row.chars().count())
+            .max()
+            .unwrap_or(0);
+        let height = self.layers.iter().map(|l| l.len()).max().unwrap_or(0);
+        // puzzles always have a base and an interactables layer
+        let mut grid = TileGrid::empty(width, height, self.layers.len().max(2));
+
+        for (z, layer) in self.layers.iter().enumerate() {
+            for (y, row) in layer.iter().enumerate() {
+                for (x, c) in row.chars().enumerate() {
+                    if c == EMPTY {
+                        continue;
+                    }
+                    match self.legend.get(&c) {
+                        Some(tile) => grid[x][y][z] = Some(*tile),
+                        None => return Err(LevelError::UnknownTile { c, x, y, z }),
+                    }
+                }
+            }
+        }
+
+        for (i, (x, y)) in self.starts.iter().enumerate() {
+            if *x >= width || *y >= height {
+                return Err(LevelError::StartOutOfBounds { x: *x, y: *y });
+            }
+            grid[*x][*y][1] = Some(TuesdayTile::PlayerStart(i as u8 + 1));
+        }
+
+        Ok(Puzzle {
+            grid,
+            starting_links: self
+                .links
+                .iter()
+                .map(|l| match l.select {
+                    true => ControlLink::selector(l.source, l.target),
+                    false => ControlLink::new(l.source, l.target),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct LevelAssetLoader;
+
+impl AssetLoader for LevelAssetLoader {
+    type Asset = LevelAsset;
+    type Settings = ();
+    type Error = LevelError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        _load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let level = LevelAsset::from_ron(&bytes)?;
+        // catch mistakes in the level when it loads rather than when it's spawned
+        level.to_puzzle()?;
+        Ok(level)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
+
+/// Custom command to spawn a level from a file in the assets folder. The level is spawned as soon
+/// as it has finished loading
+pub struct SpawnLevelFromAsset {
+    pub path: String,
+}
+
+/// A level that is waiting on its file to load
+#[derive(Component)]
+pub struct PendingLevel(pub Handle<LevelAsset>);
+
+impl Command for SpawnLevelFromAsset {
+    fn apply(self, world: &mut World) {
+        let handle = world.resource::<AssetServer>().load(self.path);
+        world.spawn(PendingLevel(handle));
+    }
+}
+
+pub fn spawn_pending_levels(
+    pending: Query<(Entity, &PendingLevel)>,
+    levels: Res<Assets<LevelAsset>>,
+    asset_server: Res<AssetServer>,
+    mut commands: Commands,
+) {
+    for (entity, PendingLevel(handle)) in pending.iter() {
+        if let Some(level) = levels.get(handle) {
+            commands.entity(entity).despawn();
+            // already checked when the level was loaded
+            let puzzle = level.to_puzzle().unwrap();
+            let biomes = level.biomes.clone();
+            debug!("Spawning level {}", level.name);
+            commands.queue(move |world: &mut World| {
+                let seed = world.resource::<RngSeed>().0;
+                spawn_puzzle(world, puzzle, &biomes, &mut ChaCha8Rng::seed_from_u64(seed));
+            });
+        } else if asset_server.load_state(handle).is_failed() {
+            warn!("Could not load level {:?}", handle.path());
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::special::starter_room::starter_room;
+
+    #[test]
+    fn starter_room_file() {
+        let level =
+            LevelAsset::from_ron(include_bytes!("../../assets/levels/starter_room.level.ron"))
+                .unwrap();
+        assert_eq!(level.par, Some(2));
+
+        let puzzle = level.to_puzzle().unwrap();
+        let expected = starter_room();
+        assert_eq!(puzzle.grid, expected.grid);
+        let links = |p: &Puzzle<TuesdayTile>| {
+            p.starting_links
+                .iter()
+                .map(|l| (l.source, l.target))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(links(&puzzle), links(&expected));
+    }
+
+    #[test]
+    fn level_errors() {
+        let level = LevelAsset::from_ron(
+            br#"(
+                name: "broken",
+                legend: { '.': Floor },
+                layers: [[ "..", ".x" ]],
+                starts: [(0, 0)],
+            )"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            level.to_puzzle(),
+            Err(LevelError::UnknownTile {
+                c: 'x',
+                x: 1,
+                y: 1,
+                z: 0
+            })
+        ));
+
+        assert!(matches!(
+            LevelAsset::from_ron(b"(name: \"no legend\")"),
+            Err(LevelError::Parse(_))
+        ));
+    }
+}
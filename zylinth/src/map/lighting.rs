@@ -1,50 +1,144 @@
-use std::usize;
+use std::f32::consts::TAU;
 
-use super::tilemap::Tile;
-use super::tuesday::{TuesdayTile, TuesdayTile::*};
+use super::tilemap::{Tile, Tilemap};
+use super::tileset::{LightAnimation, TileAmbient, Tileset};
+use crate::player::Player;
+use bevy::color::palettes::tailwind::GRAY_200;
 use bevy::prelude::*;
-use bevy_lit::prelude::PointLight2d;
+use bevy_lit::prelude::{AmbientLight2d, PointLight2d};
 
-pub fn spot_lights(
-    tiles: Query<(&Tile, &Sprite, Entity), Changed<Sprite>>,
-    mut commands: Commands,
-) {
-    for (tile, sprite, entity) in tiles.iter() {
-        if tile.tileset_name == TuesdayTile::name() {
-            let index = sprite
-                .texture_atlas
-                .as_ref()
-                .map(|atlas| atlas.index)
-                .unwrap_or(usize::MAX);
-
-            if PanelDisabled(1) == index {
-                commands.entity(entity).remove::<PointLight2d>();
-                commands.entity(entity).insert((PointLight2d {
-                    color: Color::srgb(1., 0., 0.),
-                    radius: 30.0,
-                    intensity: 4.0,
-                    falloff: 8.0,
-                    ..default()
-                },));
-            } else if PanelEnabled(1) == index {
-                commands.entity(entity).remove::<PointLight2d>();
-                commands.entity(entity).insert((PointLight2d {
-                    color: Color::srgb(0., 1., 0.),
-                    radius: 30.0,
-                    intensity: 4.0,
-                    falloff: 8.0,
-                    ..default()
-                },));
-            } else if Resoursce1 == index {
-                commands.entity(entity).remove::<PointLight2d>();
-                commands.entity(entity).insert((PointLight2d {
-                    color: Color::srgb(21. / 255., 137. / 255., 104. / 255.),
-                    radius: 60.0,
-                    intensity: 4.0,
-                    falloff: 8.0,
-                    ..default()
-                },));
+/// A light which dims and brightens over time
+#[derive(Component, Debug)]
+pub struct AnimatedLight {
+    pub intensity: f32,
+    pub animation: LightAnimation,
+    /// Seconds to offset the animation by so lights don't all move together
+    pub offset: f32,
+}
+
+/// Ambient light used when the player isn't in a room with its own
+pub fn default_ambient() -> AmbientLight2d {
+    AmbientLight2d {
+        brightness: 0.15,
+        color: Color::from(GRAY_200),
+    }
+}
+
+/// Set up the light of each tile when it's spawned or the tile it shows changes
+pub fn spot_lights(tiles: Query<(&Tile, Entity), Changed<Tile>>, mut commands: Commands) {
+    for (tile, entity) in tiles.iter() {
+        let mut entity = commands.entity(entity);
+        entity.remove::<(PointLight2d, AnimatedLight)>();
+        if let Some(light) = tile.light {
+            let (r, g, b) = light.color;
+            entity.insert(PointLight2d {
+                color: Color::srgb_u8(r, g, b),
+                radius: light.radius,
+                intensity: light.intensity,
+                falloff: light.falloff,
+                ..default()
+            });
+            if let Some(animation) = light.animation {
+                entity.insert(AnimatedLight {
+                    intensity: light.intensity,
+                    animation,
+                    offset: entity.id().index() as f32 * 0.37,
+                });
+            }
+        }
+    }
+}
+
+pub fn animate_lights(time: Res<Time>, mut lights: Query<(&AnimatedLight, &mut PointLight2d)>) {
+    for (animated, mut light) in lights.iter_mut() {
+        let t = time.elapsed_secs() + animated.offset;
+        light.intensity = animated.intensity * animated.animation.scale(t);
+    }
+}
+
+impl LightAnimation {
+    /// Fraction of the light's intensity at `t` seconds
+    pub fn scale(&self, t: f32) -> f32 {
+        match *self {
+            LightAnimation::Flicker { rate, min } => {
+                let step = (t * rate).floor();
+                let noise = ((step * 12.9898).sin() * 43758.547).fract().abs();
+                if noise < 0.25 { min } else { 1.0 }
+            }
+            LightAnimation::Pulse { period, min } => {
+                let wave = 0.5 + 0.5 * (t / period * TAU).cos();
+                min + (1.0 - min) * wave
             }
         }
     }
 }
+
+/// Switch the ambient light to the one of the room the player is standing in
+pub fn room_ambient(
+    player: Query<&GlobalTransform, (With<Player>, Changed<GlobalTransform>)>,
+    tilemaps: Query<(&Tilemap, &GlobalTransform)>,
+    tilesets: Res<Assets<Tileset>>,
+    mut ambients: Query<&mut AmbientLight2d>,
+) {
+    let Ok(player) = player.get_single() else {
+        return;
+    };
+    let room = tilemaps.iter().find_map(|(tilemap, transform)| {
+        let tileset = tilesets.get(&tilemap.config.tileset)?;
+        let position = transform
+            .affine()
+            .inverse()
+            .transform_point3(player.translation());
+        tilemap.tile_at(position.truncate(), tileset)?;
+        Some(tileset.ambient)
+    });
+    let Some(room) = room else {
+        return;
+    };
+
+    let target = match room {
+        Some(TileAmbient { color, brightness }) => AmbientLight2d {
+            color: Color::srgb_u8(color.0, color.1, color.2),
+            brightness,
+        },
+        None => default_ambient(),
+    };
+    for mut ambient in ambients.iter_mut() {
+        if ambient.color != target.color || ambient.brightness != target.brightness {
+            *ambient = target.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn animations_stay_in_range() {
+        let flicker = LightAnimation::Flicker {
+            rate: 8.0,
+            min: 0.3,
+        };
+        let pulse = LightAnimation::Pulse {
+            period: 2.0,
+            min: 0.5,
+        };
+        let samples = (0..1000).map(|i| i as f32 * 0.01);
+        let flickers: Vec<f32> = samples.clone().map(|t| flicker.scale(t)).collect();
+        assert!(flickers.iter().all(|s| *s == 0.3 || *s == 1.0));
+        assert!(flickers.contains(&0.3) && flickers.contains(&1.0));
+        // holds steady between flickers
+        assert_eq!(flicker.scale(0.01), flicker.scale(0.1));
+
+        assert!(
+            samples
+                .map(|t| pulse.scale(t))
+                .all(|s| (0.5..=1.0).contains(&s))
+        );
+        let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+        assert!(close(pulse.scale(0.0), 1.0));
+        assert!(close(pulse.scale(1.0), 0.5));
+        assert!(close(pulse.scale(2.0), 1.0));
+    }
+}
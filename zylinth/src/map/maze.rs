@@ -1,3 +1,5 @@
+use std::collections::{HashMap, VecDeque};
+
 use linked_hash_set::LinkedHashSet;
 use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
@@ -14,9 +16,85 @@ pub struct Maze {
     pub edges: LinkedHashSet<(u32, u32)>,
 }
 
+/// How the maze should be carved. All of these produce a perfect maze (exactly one route between
+/// any two nodes), which can then be braided to add loops
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MazeAlgorithm {
+    /// Long winding corridors with few branches
+    RecursiveBacktracker,
+    /// Lots of short dead ends branching off from everywhere
+    Prim,
+    /// Evenly spread branches with no bias towards the start
+    Kruskal,
+    /// Uniformly picks from every possible maze
+    Wilson,
+    /// Mix between the backtracker and Prim. A bias of 1.0 always grows from the newest node
+    /// (backtracker), 0.0 grows from a random node (Prim)
+    GrowingTree { newest_bias: f32 },
+}
+
+/// Shape of a maze's routes
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MazeStats {
+    /// Nodes with only one connection
+    pub dead_ends: u32,
+    /// Nodes with two connections
+    pub corridors: u32,
+    /// Nodes with three or more connections
+    pub junctions: u32,
+    /// How many edges there are beyond what is needed to connect every node
+    pub loops: u32,
+    /// Number of nodes on the longest route through the maze
+    pub longest_path: u32,
+}
+
 impl Maze {
     #[allow(unused)]
     pub fn generate(width: u16, height: u16, rng: &mut ChaCha8Rng) -> Maze {
+        Self::generate_with(width, height, MazeAlgorithm::RecursiveBacktracker, rng)
+    }
+
+    #[allow(unused)]
+    pub fn generate_with(
+        width: u16,
+        height: u16,
+        algorithm: MazeAlgorithm,
+        rng: &mut ChaCha8Rng,
+    ) -> Maze {
+        let total: u32 = u32::from(width) * u32::from(height);
+
+        // randomly pick a starting node in the first row
+        let start_node = (0..width).choose(rng).unwrap() as u32;
+
+        let edges = match algorithm {
+            MazeAlgorithm::RecursiveBacktracker => {
+                Self::recursive_backtracker(start_node, width, height, rng)
+            }
+            MazeAlgorithm::Prim => Self::prim(start_node, width, height, rng),
+            MazeAlgorithm::Kruskal => Self::kruskal(width, height, rng),
+            MazeAlgorithm::Wilson => Self::wilson(start_node, width, height, rng),
+            MazeAlgorithm::GrowingTree { newest_bias } => {
+                Self::growing_tree(start_node, newest_bias, width, height, rng)
+            }
+        };
+
+        Maze {
+            width,
+            height,
+            edges,
+            start_node,
+            node_count: total,
+        }
+    }
+
+    fn recursive_backtracker(
+        start_node: u32,
+        width: u16,
+        height: u16,
+        rng: &mut ChaCha8Rng,
+    ) -> LinkedHashSet<(u32, u32)> {
         let mut connected_nodes = LinkedHashSet::<u32>::new();
         let mut unconnected_nodes = LinkedHashSet::<u32>::new();
         let total: u32 = u32::from(width) * u32::from(height);
@@ -26,9 +104,6 @@ impl Maze {
 
         let mut nodes_with_only_one_connection: Vec<u32> = vec![];
         let mut edges: LinkedHashSet<(u32, u32)> = LinkedHashSet::new();
-
-        // randomly pick a starting node in the first row
-        let start_node = (0..width).choose(rng).unwrap() as u32;
         let mut current_node = start_node.clone();
 
         while unconnected_nodes.len() > 0 {
@@ -56,13 +131,318 @@ impl Maze {
             }
         }
 
-        return Maze {
-            width,
-            height,
-            edges,
-            start_node,
-            node_count: total,
+        edges
+    }
+
+    /// Grow outwards from the start by picking random edges on the frontier
+    fn prim(
+        start_node: u32,
+        width: u16,
+        height: u16,
+        rng: &mut ChaCha8Rng,
+    ) -> LinkedHashSet<(u32, u32)> {
+        let mut edges: LinkedHashSet<(u32, u32)> = LinkedHashSet::new();
+        let mut in_maze = LinkedHashSet::<u32>::new();
+        in_maze.insert(start_node);
+
+        let mut frontier: Vec<(u32, u32)> = Self::adjacent_nodes(start_node, width, height)
+            .into_iter()
+            .map(|n| (start_node, n))
+            .collect();
+
+        while !frontier.is_empty() {
+            let index = (0..frontier.len()).choose(rng).unwrap();
+            let (from, to) = frontier.swap_remove(index);
+            if in_maze.contains(&to) {
+                continue;
+            }
+            edges.insert((from, to));
+            in_maze.insert(to);
+            for next in Self::adjacent_nodes(to, width, height) {
+                if !in_maze.contains(&next) {
+                    frontier.push((to, next));
+                }
+            }
+        }
+
+        edges
+    }
+
+    /// Join randomly ordered walls between nodes that aren't already connected
+    fn kruskal(width: u16, height: u16, rng: &mut ChaCha8Rng) -> LinkedHashSet<(u32, u32)> {
+        let total = u32::from(width) * u32::from(height);
+        let mut candidates: Vec<(u32, u32)> = (0..total)
+            .flat_map(|node| {
+                Self::adjacent_nodes(node, width, height)
+                    .into_iter()
+                    .filter(move |n| *n > node)
+                    .map(move |n| (node, n))
+            })
+            .collect();
+        candidates.shuffle(rng);
+
+        // union-find of which set each node belongs to
+        let mut parents: Vec<u32> = (0..total).collect();
+        fn root(parents: &mut [u32], node: u32) -> u32 {
+            let mut node = node;
+            while parents[node as usize] != node {
+                parents[node as usize] = parents[parents[node as usize] as usize];
+                node = parents[node as usize];
+            }
+            node
+        }
+
+        let mut edges: LinkedHashSet<(u32, u32)> = LinkedHashSet::new();
+        for (a, b) in candidates {
+            let (root_a, root_b) = (root(&mut parents, a), root(&mut parents, b));
+            if root_a != root_b {
+                parents[root_b as usize] = root_a;
+                edges.insert((a, b));
+            }
+        }
+
+        edges
+    }
+
+    /// Loop-erased random walks from every unconnected node until they hit the maze
+    fn wilson(
+        start_node: u32,
+        width: u16,
+        height: u16,
+        rng: &mut ChaCha8Rng,
+    ) -> LinkedHashSet<(u32, u32)> {
+        let total = u32::from(width) * u32::from(height);
+        let mut edges: LinkedHashSet<(u32, u32)> = LinkedHashSet::new();
+        let mut in_maze = LinkedHashSet::<u32>::new();
+        in_maze.insert(start_node);
+
+        let mut remaining: Vec<u32> = (0..total).filter(|n| *n != start_node).collect();
+        remaining.shuffle(rng);
+
+        for walk_start in remaining {
+            if in_maze.contains(&walk_start) {
+                continue;
+            }
+
+            // walk until the maze is hit, remembering only the last exit from each node
+            // which erases any loops in the walk
+            let mut exits: HashMap<u32, u32> = HashMap::new();
+            let mut current = walk_start;
+            while !in_maze.contains(&current) {
+                let next = *Self::adjacent_nodes(current, width, height)
+                    .choose(rng)
+                    .unwrap();
+                exits.insert(current, next);
+                current = next;
+            }
+
+            let mut current = walk_start;
+            while !in_maze.contains(&current) {
+                let next = exits[&current];
+                edges.insert((current, next));
+                in_maze.insert(current);
+                current = next;
+            }
+        }
+
+        edges
+    }
+
+    fn growing_tree(
+        start_node: u32,
+        newest_bias: f32,
+        width: u16,
+        height: u16,
+        rng: &mut ChaCha8Rng,
+    ) -> LinkedHashSet<(u32, u32)> {
+        let mut edges: LinkedHashSet<(u32, u32)> = LinkedHashSet::new();
+        let mut in_maze = LinkedHashSet::<u32>::new();
+        in_maze.insert(start_node);
+        let mut active: Vec<u32> = vec![start_node];
+
+        while !active.is_empty() {
+            let index = if rng.random_bool(newest_bias.clamp(0.0, 1.0) as f64) {
+                active.len() - 1
+            } else {
+                (0..active.len()).choose(rng).unwrap()
+            };
+            let node = active[index];
+            let unvisited = Self::adjacent_nodes(node, width, height)
+                .into_iter()
+                .filter(|n| !in_maze.contains(n))
+                .collect::<Vec<_>>();
+
+            match unvisited.choose(rng) {
+                Some(next) => {
+                    edges.insert((node, *next));
+                    in_maze.insert(*next);
+                    active.push(*next);
+                }
+                None => {
+                    active.remove(index);
+                }
+            }
+        }
+
+        edges
+    }
+
+    /// Remove a fraction of the dead ends by connecting them to a neighbor, creating loops.
+    /// 0.0 leaves the maze alone, 1.0 removes every dead end
+    #[allow(unused)]
+    pub fn braid(&mut self, factor: f32, rng: &mut ChaCha8Rng) {
+        let mut dead_ends = self.dead_ends();
+        dead_ends.shuffle(rng);
+
+        for node in dead_ends {
+            // an earlier pass may have already joined this one
+            if self.neighbors(node).len() != 1 || !rng.random_bool(factor.clamp(0.0, 1.0) as f64) {
+                continue;
+            }
+
+            let connected = self.neighbors(node);
+            let candidates = Self::adjacent_nodes(node, self.width, self.height)
+                .into_iter()
+                .filter(|n| !connected.contains(n))
+                .collect::<Vec<_>>();
+
+            // prefer joining two dead ends together since that removes both
+            let other_dead_ends = candidates
+                .iter()
+                .filter(|n| self.neighbors(**n).len() == 1)
+                .copied()
+                .collect::<Vec<_>>();
+            let target = other_dead_ends.choose(rng).or(candidates.choose(rng));
+            if let Some(target) = target {
+                self.edges.insert((node, *target));
+            }
+        }
+    }
+
+    /// Get the nodes that this node has an edge to, in either direction
+    #[allow(unused)]
+    pub fn neighbors(&self, node: u32) -> Vec<u32> {
+        self.edges
+            .iter()
+            .filter_map(|(a, b)| {
+                if *a == node {
+                    Some(*b)
+                } else if *b == node {
+                    Some(*a)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// All nodes with only a single connection
+    #[allow(unused)]
+    pub fn dead_ends(&self) -> Vec<u32> {
+        let degrees = self.degrees();
+        (0..self.node_count)
+            .filter(|n| degrees.get(n).copied().unwrap_or(0) == 1)
+            .collect()
+    }
+
+    /// The longest of the shortest routes between any two nodes, starting from one end.
+    /// For a perfect maze this is the longest route that exists
+    #[allow(unused)]
+    pub fn longest_path(&self) -> Vec<u32> {
+        if self.node_count == 0 {
+            return vec![];
+        }
+        let adjacency = self.adjacency();
+        let (far_end, _) = Self::farthest(self.start_node, &adjacency);
+        let (other_end, previous) = Self::farthest(far_end, &adjacency);
+
+        let mut path = vec![other_end];
+        let mut current = other_end;
+        while let Some(prev) = previous.get(&current) {
+            path.push(*prev);
+            current = *prev;
+        }
+        path
+    }
+
+    #[allow(unused)]
+    pub fn stats(&self) -> MazeStats {
+        let degrees = self.degrees();
+        let count = |f: fn(usize) -> bool| {
+            (0..self.node_count)
+                .filter(|n| f(degrees.get(n).copied().unwrap_or(0)))
+                .count() as u32
         };
+
+        MazeStats {
+            dead_ends: count(|d| d == 1),
+            corridors: count(|d| d == 2),
+            junctions: count(|d| d >= 3),
+            loops: (self.edges.len() as u32 + 1).saturating_sub(self.node_count),
+            longest_path: self.longest_path().len() as u32,
+        }
+    }
+
+    fn degrees(&self) -> HashMap<u32, usize> {
+        let mut degrees: HashMap<u32, usize> = HashMap::new();
+        for (a, b) in self.edges.iter() {
+            *degrees.entry(*a).or_default() += 1;
+            *degrees.entry(*b).or_default() += 1;
+        }
+        degrees
+    }
+
+    fn adjacency(&self) -> HashMap<u32, Vec<u32>> {
+        let mut adjacency: HashMap<u32, Vec<u32>> = HashMap::new();
+        for (a, b) in self.edges.iter() {
+            adjacency.entry(*a).or_default().push(*b);
+            adjacency.entry(*b).or_default().push(*a);
+        }
+        adjacency
+    }
+
+    /// Breadth first search returning the farthest node and how each node was reached
+    fn farthest(from: u32, adjacency: &HashMap<u32, Vec<u32>>) -> (u32, HashMap<u32, u32>) {
+        let mut previous: HashMap<u32, u32> = HashMap::new();
+        let mut visited = LinkedHashSet::<u32>::new();
+        let mut queue = VecDeque::from([from]);
+        visited.insert(from);
+        let mut last = from;
+
+        while let Some(node) = queue.pop_front() {
+            last = node;
+            for next in adjacency.get(&node).into_iter().flatten() {
+                if visited.insert(*next) {
+                    previous.insert(*next, node);
+                    queue.push_back(*next);
+                }
+            }
+        }
+
+        (last, previous)
+    }
+
+    /// Get the nodes next to this one on the grid, whether or not they are connected
+    pub fn adjacent_nodes(node: u32, width: u16, height: u16) -> Vec<u32> {
+        let width = width as u32;
+        let height = height as u32;
+        let y = node / width;
+        let x = node - (y * width);
+
+        let mut adjacent = Vec::with_capacity(4);
+        if x > 0 {
+            adjacent.push(node - 1);
+        }
+        if x + 1 < width {
+            adjacent.push(node + 1);
+        }
+        if y > 0 {
+            adjacent.push(node - width);
+        }
+        if y + 1 < height {
+            adjacent.push(node + width);
+        }
+        adjacent
     }
 
     // Given a node ID, get the possible edges that it can connect to
@@ -180,6 +560,85 @@ mod tests {
         assert_eq!(edges.len(), 2);
     }
 
+    fn assert_perfect(maze: &Maze) {
+        assert_eq!(maze.edges.len() as u32, maze.node_count - 1);
+        let adjacency = maze.adjacency();
+        let mut visited = LinkedHashSet::new();
+        let mut stack = vec![maze.start_node];
+        while let Some(node) = stack.pop() {
+            if visited.insert(node) {
+                stack.extend(adjacency.get(&node).into_iter().flatten());
+            }
+        }
+        assert_eq!(visited.len() as u32, maze.node_count);
+    }
+
+    #[test]
+    fn algorithms_are_perfect() {
+        let algorithms = [
+            MazeAlgorithm::RecursiveBacktracker,
+            MazeAlgorithm::Prim,
+            MazeAlgorithm::Kruskal,
+            MazeAlgorithm::Wilson,
+            MazeAlgorithm::GrowingTree { newest_bias: 0.5 },
+        ];
+        for algorithm in algorithms {
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            let maze = Maze::generate_with(6, 5, algorithm, &mut rng);
+            assert_perfect(&maze);
+            assert_eq!(maze.stats().loops, 0, "{:?}", algorithm);
+        }
+    }
+
+    #[test]
+    fn braid_removes_dead_ends() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut maze = Maze::generate_with(6, 5, MazeAlgorithm::Prim, &mut rng);
+        assert!(!maze.dead_ends().is_empty());
+
+        maze.braid(1.0, &mut rng);
+        assert_eq!(maze.dead_ends().len(), 0);
+        assert!(maze.stats().loops > 0);
+    }
+
+    #[test]
+    fn braid_none() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mut maze = Maze::generate_with(6, 5, MazeAlgorithm::Prim, &mut rng);
+        let dead_ends = maze.dead_ends().len();
+        maze.braid(0.0, &mut rng);
+        assert_eq!(maze.dead_ends().len(), dead_ends);
+    }
+
+    #[test]
+    fn stats() {
+        // 0 - 1 - 2
+        //     |
+        // 3 - 4 - 5
+        let mut edges = LinkedHashSet::new();
+        edges.insert((0, 1));
+        edges.insert((1, 2));
+        edges.insert((1, 4));
+        edges.insert((3, 4));
+        edges.insert((4, 5));
+        let mut maze = Maze {
+            width: 3,
+            height: 2,
+            start_node: 0,
+            node_count: 6,
+            edges,
+        };
+        let stats = maze.stats();
+        assert_eq!(stats.dead_ends, 4);
+        assert_eq!(stats.junctions, 2);
+        assert_eq!(stats.corridors, 0);
+        assert_eq!(stats.loops, 0);
+        assert_eq!(stats.longest_path, 4);
+
+        maze.edges.insert((0, 3));
+        assert_eq!(maze.stats().loops, 1);
+    }
+
     // // TODO: fix
     // #[test]
     // fn node_id_to_grid_coords() {
@@ -0,0 +1,339 @@
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use tilegen::{TileDir, TileGrid, TileLine, TileRect, TileRegion};
+
+use super::maze::{Maze, MazeAlgorithm};
+use super::puzzle::Puzzle;
+use super::tuesday::TuesdayTile;
+
+/// Size of the space each maze node gets in the building
+pub const MAZE_CELL_WIDTH: usize = 12;
+pub const MAZE_CELL_HEIGHT: usize = 10;
+
+/// Empty space kept between rooms in neighboring cells so there is room to wrap walls around them
+/// and for corridors to jog without running alongside a room
+const ROOM_GAP: usize = 4;
+const MIN_ROOM_SIZE: usize = 3;
+const CORRIDOR_WIDTH: usize = 2;
+/// Smallest cell with space for a room and the gap a corridor jogs through
+const MIN_CELL_SIZE: usize = ROOM_GAP + MIN_ROOM_SIZE;
+
+/// Lay out one room per maze node and carve a corridor for each edge.
+/// Every node gets a `cell_width` x `cell_height` cell which its room is randomly sized and placed within.
+/// The region type of each returned region is the node it was made for.
+/// Cells smaller than `MIN_CELL_SIZE` are grown to it.
+pub fn maze_to_rooms<T: Clone + PartialEq + Eq>(
+    maze: &Maze,
+    cell_width: usize,
+    cell_height: usize,
+    floor: T,
+    rng: &mut ChaCha8Rng,
+) -> (TileGrid<T>, Vec<TileRegion<u32>>) {
+    let cell_width = cell_width.max(MIN_CELL_SIZE);
+    let cell_height = cell_height.max(MIN_CELL_SIZE);
+    let maze_width = maze.width as usize;
+    let mut grid = TileGrid::empty(
+        maze_width * cell_width,
+        maze.height as usize * cell_height,
+        1,
+    );
+
+    let mut regions = (0..maze.node_count)
+        .map(|node| {
+            let col = node as usize % maze_width;
+            let row = node as usize / maze_width;
+            let max_width = cell_width.saturating_sub(ROOM_GAP).max(MIN_ROOM_SIZE);
+            let max_height = cell_height.saturating_sub(ROOM_GAP).max(MIN_ROOM_SIZE);
+            let width = (MIN_ROOM_SIZE..=max_width).choose(rng).unwrap();
+            let height = (MIN_ROOM_SIZE..=max_height).choose(rng).unwrap();
+            let x = col * cell_width + (0..=max_width - width).choose(rng).unwrap();
+            let y = row * cell_height + (0..=max_height - height).choose(rng).unwrap();
+            TileRegion::new(node, TileRect::new(x, y, x + width, y + height))
+        })
+        .collect::<Vec<_>>();
+
+    for region in regions.iter() {
+        carve(&mut grid, &region.rect, &floor);
+    }
+
+    for (a, b) in maze.edges.iter() {
+        // always carve from the top/left node to the bottom/right one
+        let (a, b) = (*a.min(b) as usize, *a.max(b) as usize);
+        let horizontal = b == a + 1 && a / maze_width == b / maze_width;
+        let (from, to) = (regions[a].rect.clone(), regions[b].rect.clone());
+
+        if horizontal {
+            let (from_y, to_y) = corridor_ends(from.min.y, from.max.y, to.min.y, to.max.y, rng);
+            // keep the jog away from both rooms so each corridor mouth is only as wide as the corridor
+            let mid_x = (from.max.x + 1..=to.min.x - CORRIDOR_WIDTH - 1)
+                .choose(rng)
+                .unwrap();
+            carve_z(
+                &mut grid,
+                &floor,
+                (from.max.x, from_y),
+                mid_x,
+                (to.min.x, to_y),
+            );
+            regions[a].add_exit(
+                TileDir::Right,
+                TileLine::new(from.max.x, from_y, from.max.x, from_y + CORRIDOR_WIDTH),
+            );
+            regions[b].add_exit(
+                TileDir::Left,
+                TileLine::new(to.min.x, to_y, to.min.x, to_y + CORRIDOR_WIDTH),
+            );
+        } else {
+            let (from_x, to_x) = corridor_ends(from.min.x, from.max.x, to.min.x, to.max.x, rng);
+            let mid_y = (from.max.y + 1..=to.min.y - CORRIDOR_WIDTH - 1)
+                .choose(rng)
+                .unwrap();
+            carve_n(
+                &mut grid,
+                &floor,
+                (from_x, from.max.y),
+                mid_y,
+                (to_x, to.min.y),
+            );
+            regions[a].add_exit(
+                TileDir::Down,
+                TileLine::new(from_x, from.max.y, from_x + CORRIDOR_WIDTH, from.max.y),
+            );
+            regions[b].add_exit(
+                TileDir::Up,
+                TileLine::new(to_x, to.min.y, to_x + CORRIDOR_WIDTH, to.min.y),
+            );
+        }
+    }
+
+    (grid, regions)
+}
+
+/// Generate a building where every room is a node of a maze
+pub fn maze_building(
+    width: usize,
+    height: usize,
+    algorithm: MazeAlgorithm,
+    braid: f32,
+    rng: &mut ChaCha8Rng,
+) -> Puzzle<TuesdayTile> {
+    let (maze_width, maze_height) = maze_dimensions(width, height);
+    let mut maze = Maze::generate_with(maze_width, maze_height, algorithm, rng);
+    maze.braid(braid, rng);
+
+    let (mut grid, _) = maze_to_rooms(
+        &maze,
+        MAZE_CELL_WIDTH,
+        MAZE_CELL_HEIGHT,
+        TuesdayTile::Floor,
+        rng,
+    );
+    grid.push_layer();
+
+    Puzzle {
+        grid,
+        starting_links: vec![],
+    }
+}
+
+/// How many maze nodes fit into a building of the given size
+pub fn maze_dimensions(width: usize, height: usize) -> (u16, u16) {
+    (
+        (width / MAZE_CELL_WIDTH).max(1) as u16,
+        (height / MAZE_CELL_HEIGHT).max(1) as u16,
+    )
+}
+
+/// Pick where a corridor leaves one room and enters the other. When the rooms overlap enough the
+/// corridor is kept straight
+fn corridor_ends(
+    from_min: usize,
+    from_max: usize,
+    to_min: usize,
+    to_max: usize,
+    rng: &mut ChaCha8Rng,
+) -> (usize, usize) {
+    let overlap_min = from_min.max(to_min);
+    let overlap_max = from_max.min(to_max);
+    if overlap_max >= overlap_min + CORRIDOR_WIDTH {
+        let pos = (overlap_min..=overlap_max - CORRIDOR_WIDTH)
+            .choose(rng)
+            .unwrap();
+        return (pos, pos);
+    }
+
+    (
+        (from_min..=from_max - CORRIDOR_WIDTH).choose(rng).unwrap(),
+        (to_min..=to_max - CORRIDOR_WIDTH).choose(rng).unwrap(),
+    )
+}
+
+/// Carve a horizontal corridor that jogs vertically at `mid_x`
+fn carve_z<T: Clone + PartialEq + Eq>(
+    grid: &mut TileGrid<T>,
+    floor: &T,
+    from: (usize, usize),
+    mid_x: usize,
+    to: (usize, usize),
+) {
+    let (x0, y0) = from;
+    let (x1, y1) = to;
+    carve(
+        grid,
+        &TileRect::new(x0, y0, mid_x + CORRIDOR_WIDTH, y0 + CORRIDOR_WIDTH),
+        floor,
+    );
+    carve(
+        grid,
+        &TileRect::new(
+            mid_x,
+            y0.min(y1),
+            mid_x + CORRIDOR_WIDTH,
+            y0.max(y1) + CORRIDOR_WIDTH,
+        ),
+        floor,
+    );
+    carve(
+        grid,
+        &TileRect::new(mid_x, y1, x1, y1 + CORRIDOR_WIDTH),
+        floor,
+    );
+}
+
+/// Carve a vertical corridor that jogs horizontally at `mid_y`
+fn carve_n<T: Clone + PartialEq + Eq>(
+    grid: &mut TileGrid<T>,
+    floor: &T,
+    from: (usize, usize),
+    mid_y: usize,
+    to: (usize, usize),
+) {
+    let (x0, y0) = from;
+    let (x1, y1) = to;
+    carve(
+        grid,
+        &TileRect::new(x0, y0, x0 + CORRIDOR_WIDTH, mid_y + CORRIDOR_WIDTH),
+        floor,
+    );
+    carve(
+        grid,
+        &TileRect::new(
+            x0.min(x1),
+            mid_y,
+            x0.max(x1) + CORRIDOR_WIDTH,
+            mid_y + CORRIDOR_WIDTH,
+        ),
+        floor,
+    );
+    carve(
+        grid,
+        &TileRect::new(x1, mid_y, x1 + CORRIDOR_WIDTH, y1),
+        floor,
+    );
+}
+
+fn carve<T: Clone + PartialEq + Eq>(grid: &mut TileGrid<T>, rect: &TileRect, floor: &T) {
+    for x in rect.min.x..rect.max.x {
+        for y in rect.min.y..rect.max.y {
+            grid[x][y][0] = Some(floor.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::decoration::decorate_empty;
+    use crate::map::invariants::lint_building;
+    use crate::map::starter::{mark_exit_tile, mark_player_start_tile};
+    use crate::map::tuesday::TuesdayTile::*;
+    use crate::map::wall_wrap::wrap_walls;
+
+    /// Count every floor tile reachable from the first one
+    fn flood_fill(grid: &TileGrid<u8>) -> usize {
+        let start = (0..grid.width())
+            .flat_map(|x| (0..grid.height()).map(move |y| (x, y)))
+            .find(|(x, y)| grid[*x][*y][0].is_some())
+            .unwrap();
+        let mut seen = vec![vec![false; grid.height()]; grid.width()];
+        let mut stack = vec![start];
+        let mut count = 0;
+        while let Some((x, y)) = stack.pop() {
+            if seen[x][y] || grid[x][y][0].is_none() {
+                continue;
+            }
+            seen[x][y] = true;
+            count += 1;
+            if x > 0 {
+                stack.push((x - 1, y));
+            }
+            if y > 0 {
+                stack.push((x, y - 1));
+            }
+            if x + 1 < grid.width() {
+                stack.push((x + 1, y));
+            }
+            if y + 1 < grid.height() {
+                stack.push((x, y + 1));
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn rooms_are_connected() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let mut maze = Maze::generate_with(4, 3, MazeAlgorithm::Kruskal, &mut rng);
+        maze.braid(0.5, &mut rng);
+        let (grid, regions) = maze_to_rooms(&maze, 12, 10, 1u8, &mut rng);
+
+        let floors = grid
+            .iter()
+            .flatten()
+            .filter(|stack| stack[0].is_some())
+            .count();
+        assert_eq!(flood_fill(&grid), floors);
+
+        assert_eq!(regions.len(), 12);
+        for region in regions {
+            assert_eq!(region.exits.len(), maze.neighbors(region.region_type).len());
+        }
+    }
+
+    #[test]
+    fn small_cells_are_grown() {
+        for (seed, size) in [(1, 0), (2, 3), (3, 6), (4, MIN_CELL_SIZE)] {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let maze = Maze::generate_with(3, 3, MazeAlgorithm::Wilson, &mut rng);
+            let (grid, _) = maze_to_rooms(&maze, size, size, 1u8, &mut rng);
+            assert_eq!(grid.width(), 3 * MIN_CELL_SIZE);
+
+            let floors = grid
+                .iter()
+                .flatten()
+                .filter(|stack| stack[0].is_some())
+                .count();
+            assert_eq!(flood_fill(&grid), floors, "cell size {size}");
+        }
+    }
+
+    #[test]
+    fn maze_building_invariants() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let puzzle = maze_building(48, 30, MazeAlgorithm::Prim, 0.5, &mut rng);
+        let mut grid = wrap_walls(puzzle.grid, &mut rng);
+        decorate_empty(&mut grid, &mut rng);
+        mark_player_start_tile(&mut grid, 1, &mut rng);
+        mark_exit_tile(&mut grid);
+
+        let report = lint_building(&grid);
+        assert!(report.is_ok(), "{report}");
+        let exits = grid
+            .iter()
+            .flatten()
+            .flatten()
+            .filter(|t| **t == Some(Exit))
+            .count();
+        assert_eq!(exits, 1);
+    }
+}
@@ -0,0 +1,547 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use linked_hash_set::LinkedHashSet;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+
+use super::maze::{Maze, MazeAlgorithm};
+use super::maze_rooms::{
+    MAZE_CELL_HEIGHT, MAZE_CELL_WIDTH, maze_building, maze_dimensions, maze_to_rooms,
+};
+use super::puzzle::Puzzle;
+use super::puzzle_gen::{doorway, place_player_start, place_tile};
+use super::puzzle_solver::{SolveGoal, solve};
+use super::tuesday::{TuesdayTile, TuesdayTile::*};
+use super::{BASE_LAYER, INTERACTABLES_LAYER, MAX_ATTEMPTS};
+use crate::defs::ControlLink;
+
+/// Tries at fitting a mission into a maze before giving up on it
+const EMBED_ATTEMPTS: usize = 20;
+
+/// Most locks a mission can have, since every lock takes up to three ids for its door, switch and
+/// panel
+pub const MAX_LOCKS: u8 = (u8::MAX - 1) / 3;
+
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionRoom {
+    Start,
+    Room,
+    /// Where the level ends
+    Goal,
+}
+
+/// A locked door between two rooms and what opens it
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissionLock {
+    pub door: u8,
+    /// Switch that opens the door
+    pub switch: u8,
+    /// Panel next to the door which the switch has to be wired into before it does anything
+    pub panel: Option<u8>,
+    /// Room the switch is found in
+    pub key_room: usize,
+}
+
+/// A connection from a room to one further away from the start
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissionEdge {
+    pub from: usize,
+    pub to: usize,
+    pub lock: Option<MissionLock>,
+}
+
+/// Rewrites that grow a mission graph
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissionRule {
+    /// Put a new room in the middle of a connection
+    Extend,
+    /// Add a dead end room off of an existing one
+    Branch,
+    /// Lock a connection and put its switch in a new room branching off just before it. The new
+    /// room comes after the last lock on the way there, so the switch is found behind the previous door
+    LockAndKey { panel: bool },
+}
+
+/// What a generated mission should contain
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissionSpec {
+    pub locks: u8,
+    /// How many of the locks are guarded by a panel
+    pub panels: u8,
+    /// Extra rooms on top of the start, goal and one room per switch
+    pub rooms: u8,
+}
+
+impl MissionSpec {
+    /// The spec cut down to as many locks as there are ids for
+    pub fn capped(&self) -> Self {
+        let locks = self.locks.min(MAX_LOCKS);
+        Self {
+            locks,
+            panels: self.panels.min(locks),
+            ..*self
+        }
+    }
+}
+
+/// What a level is made of: rooms and the locks between them, without any idea of where the
+/// rooms physically are
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissionGraph {
+    pub rooms: Vec<MissionRoom>,
+    /// Always a tree growing out from the start room
+    pub edges: Vec<MissionEdge>,
+    next_id: u8,
+}
+
+/// Where a mission's rooms ended up in a maze
+#[allow(unused)]
+#[derive(Debug, Clone)]
+pub struct MissionLayout {
+    pub maze: Maze,
+    /// Maze node for each mission room
+    pub nodes: Vec<u32>,
+    /// Mission room each maze node is part of. Nodes which weren't needed for the mission are
+    /// tacked on to a neighboring room
+    pub owners: HashMap<u32, usize>,
+}
+
+#[allow(unused)]
+impl MissionGraph {
+    /// The smallest mission: walk from the start to the goal
+    pub fn new() -> Self {
+        Self {
+            rooms: vec![MissionRoom::Start, MissionRoom::Goal],
+            edges: vec![MissionEdge {
+                from: 0,
+                to: 1,
+                lock: None,
+            }],
+            next_id: 1,
+        }
+    }
+
+    /// Grow a mission by applying rules for the locks and rooms in the spec in a random order
+    pub fn generate(spec: &MissionSpec, rng: &mut ChaCha8Rng) -> Self {
+        let spec = spec.capped();
+        let mut rules = (0..spec.locks)
+            .map(|i| MissionRule::LockAndKey {
+                panel: i < spec.panels,
+            })
+            .collect::<Vec<_>>();
+        for _ in 0..spec.rooms {
+            rules.push(if rng.random_bool(0.5) {
+                MissionRule::Extend
+            } else {
+                MissionRule::Branch
+            });
+        }
+        rules.shuffle(rng);
+
+        let mut mission = Self::new();
+        for rule in rules {
+            mission.apply(rule, rng);
+        }
+        mission
+    }
+
+    /// Rewrite part of the graph
+    pub fn apply(&mut self, rule: MissionRule, rng: &mut ChaCha8Rng) {
+        match rule {
+            MissionRule::Extend => {
+                let edge = (0..self.edges.len()).choose(rng).unwrap();
+                self.split(edge);
+            }
+            MissionRule::Branch => {
+                let from = (0..self.rooms.len())
+                    .filter(|r| self.rooms[*r] != MissionRoom::Goal)
+                    .choose(rng)
+                    .unwrap();
+                let room = self.add_room();
+                self.edges.push(MissionEdge {
+                    from,
+                    to: room,
+                    lock: None,
+                });
+            }
+            MissionRule::LockAndKey { panel } => {
+                // always lock the way to the goal so every lock has to be opened. When it's
+                // already locked all the way a new room is made in front of the goal
+                let goal_path = self.path_to(self.goal());
+                let unlocked = (0..self.edges.len())
+                    .filter(|e| {
+                        self.edges[*e].lock.is_none() && goal_path.contains(&self.edges[*e].to)
+                    })
+                    .collect::<Vec<_>>();
+                let edge = match unlocked.choose(rng) {
+                    Some(edge) => *edge,
+                    None => {
+                        let into_goal = self.edges.iter().position(|e| e.to == self.goal());
+                        self.split(into_goal.unwrap())
+                    }
+                };
+
+                let path = self.path_to(self.edges[edge].from);
+                let after_last_lock = path
+                    .iter()
+                    .rposition(|room| self.edge_into(*room).is_some_and(|e| e.lock.is_some()))
+                    .unwrap_or(0);
+                let parent = *path[after_last_lock..].choose(rng).unwrap();
+                let key_room = self.add_room();
+                self.edges.push(MissionEdge {
+                    from: parent,
+                    to: key_room,
+                    lock: None,
+                });
+
+                let door = self.take_id();
+                let switch = self.take_id();
+                let panel = panel.then(|| self.take_id());
+                self.edges[edge].lock = Some(MissionLock {
+                    door,
+                    switch,
+                    panel,
+                    key_room,
+                });
+            }
+        }
+    }
+
+    pub fn goal(&self) -> usize {
+        self.rooms
+            .iter()
+            .position(|r| *r == MissionRoom::Goal)
+            .unwrap()
+    }
+
+    pub fn locks(&self) -> impl Iterator<Item = &MissionLock> {
+        self.edges.iter().filter_map(|e| e.lock.as_ref())
+    }
+
+    /// Rooms passed through going from the start to a room, including both of them
+    pub fn path_to(&self, room: usize) -> Vec<usize> {
+        let mut path = vec![room];
+        let mut current = room;
+        while let Some(edge) = self.edge_into(current) {
+            current = edge.from;
+            path.push(current);
+        }
+        path.reverse();
+        path
+    }
+
+    /// Whether every room can be reached by opening doors with switches that are already reachable
+    pub fn is_solvable(&self) -> bool {
+        let mut open = vec![false; self.rooms.len()];
+        open[0] = true;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for edge in self.edges.iter() {
+                let unlocked = edge.lock.is_none_or(|lock| open[lock.key_room]);
+                if open[edge.from] && !open[edge.to] && unlocked {
+                    open[edge.to] = true;
+                    changed = true;
+                }
+            }
+        }
+        open.iter().all(|o| *o)
+    }
+
+    /// How each door starts out wired. Panels are wired to their door, the player has to wire the switch
+    /// into the panel themselves
+    pub fn links(&self) -> Vec<ControlLink> {
+        self.locks()
+            .map(|lock| ControlLink::new(lock.panel.unwrap_or(lock.switch), lock.door))
+            .collect()
+    }
+
+    /// Fit the rooms into a maze of the given size so that connected rooms are next to each other.
+    /// Returns nothing if no fit was found
+    pub fn embed(&self, width: u16, height: u16, rng: &mut ChaCha8Rng) -> Option<MissionLayout> {
+        if self.rooms.len() > width as usize * height as usize {
+            return None;
+        }
+        (0..EMBED_ATTEMPTS).find_map(|_| self.try_embed(width, height, rng))
+    }
+
+    fn try_embed(&self, width: u16, height: u16, rng: &mut ChaCha8Rng) -> Option<MissionLayout> {
+        let node_count = width as u32 * height as u32;
+        let start = (0..node_count).choose(rng).unwrap();
+        let mut nodes = vec![start; self.rooms.len()];
+        let mut owners = HashMap::from([(start, 0)]);
+        let mut edges = LinkedHashSet::new();
+
+        // place each room next to the one leading into it
+        let mut queue = VecDeque::from([0]);
+        while let Some(room) = queue.pop_front() {
+            for edge in self.edges.iter().filter(|e| e.from == room) {
+                let node = *Maze::adjacent_nodes(nodes[room], width, height)
+                    .into_iter()
+                    .filter(|n| !owners.contains_key(n))
+                    .collect::<Vec<_>>()
+                    .choose(rng)?;
+                nodes[edge.to] = node;
+                owners.insert(node, edge.to);
+                edges.insert((nodes[room], node));
+                queue.push_back(edge.to);
+            }
+        }
+
+        // grow the rooms into whatever space is left over
+        loop {
+            let frontier = (0..node_count)
+                .filter(|n| !owners.contains_key(n))
+                .flat_map(|n| {
+                    Maze::adjacent_nodes(n, width, height)
+                        .into_iter()
+                        .filter(|a| owners.contains_key(a))
+                        .map(move |a| (a, n))
+                })
+                .collect::<Vec<_>>();
+            let Some((from, to)) = frontier.choose(rng).copied() else {
+                break;
+            };
+            owners.insert(to, owners[&from]);
+            edges.insert((from, to));
+        }
+
+        Some(MissionLayout {
+            maze: Maze {
+                width,
+                height,
+                start_node: start,
+                node_count,
+                edges,
+            },
+            nodes,
+            owners,
+        })
+    }
+
+    /// Put a new room in the middle of an edge, returning the edge out of the new room. Any lock
+    /// stays on the way in, so the new room is behind it too
+    fn split(&mut self, edge: usize) -> usize {
+        let room = self.add_room();
+        let to = self.edges[edge].to;
+        self.edges[edge].to = room;
+        self.edges.push(MissionEdge {
+            from: room,
+            to,
+            lock: None,
+        });
+        self.edges.len() - 1
+    }
+
+    fn edge_into(&self, room: usize) -> Option<&MissionEdge> {
+        self.edges.iter().find(|e| e.to == room)
+    }
+
+    fn add_room(&mut self) -> usize {
+        self.rooms.push(MissionRoom::Room);
+        self.rooms.len() - 1
+    }
+
+    fn take_id(&mut self) -> u8 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+}
+
+/// Lay a mission out as a building. Returns nothing if the mission doesn't fit
+pub fn mission_puzzle(
+    width: usize,
+    height: usize,
+    mission: &MissionGraph,
+    rng: &mut ChaCha8Rng,
+) -> Option<Puzzle<TuesdayTile>> {
+    let (maze_width, maze_height) = maze_dimensions(width, height);
+    let layout = mission.embed(maze_width, maze_height, rng)?;
+    let (mut grid, rooms) =
+        maze_to_rooms(&layout.maze, MAZE_CELL_WIDTH, MAZE_CELL_HEIGHT, Floor, rng);
+    grid.push_layer();
+
+    let start = place_player_start(&mut grid, &rooms[layout.nodes[0] as usize], rng);
+    for edge in mission.edges.iter() {
+        let Some(lock) = edge.lock else {
+            continue;
+        };
+        let (door, blocker) = doorway(
+            &layout.maze,
+            &rooms[layout.nodes[edge.from] as usize],
+            &rooms[layout.nodes[edge.to] as usize],
+        );
+        grid[door.x][door.y][INTERACTABLES_LAYER] = Some(DoorFrame(lock.door));
+        grid[blocker.x][blocker.y][BASE_LAYER] = None;
+        if let Some(panel) = lock.panel {
+            grid[blocker.x][blocker.y][INTERACTABLES_LAYER] = Some(PanelDisabled(panel));
+        }
+        place_tile(
+            &mut grid,
+            &rooms,
+            &layout.owners,
+            lock.key_room,
+            SwitchLeft(lock.switch),
+            &start,
+            rng,
+        );
+    }
+
+    place_tile(
+        &mut grid,
+        &rooms,
+        &layout.owners,
+        mission.goal(),
+        Exit,
+        &start,
+        rng,
+    );
+
+    Some(Puzzle {
+        grid,
+        starting_links: mission.links(),
+    })
+}
+
+/// Generate a mission and lay it out as a building, trying again with a new mission if it doesn't
+/// fit or can't be solved. Falls back to plain maze rooms if nothing works out
+pub fn generate_mission_puzzle(
+    width: usize,
+    height: usize,
+    spec: &MissionSpec,
+    rng: &mut ChaCha8Rng,
+) -> Puzzle<TuesdayTile> {
+    for attempt in 1..=MAX_ATTEMPTS {
+        let mission = MissionGraph::generate(spec, rng);
+        let Some(puzzle) = mission_puzzle(width, height, &mission, rng) else {
+            debug!("Mission {attempt} did not fit in the building, trying again");
+            continue;
+        };
+        let solvable = puzzle
+            .player_start()
+            .is_some_and(|start| solve(&puzzle, &start, &SolveGoal::Everything).is_solvable());
+        if solvable {
+            return puzzle;
+        }
+        debug!("Mission {attempt} was not solvable, trying again");
+    }
+
+    warn!("Could not lay out a mission after {MAX_ATTEMPTS} attempts, falling back to maze rooms");
+    maze_building(width, height, MazeAlgorithm::Wilson, 0.0, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::decoration::decorate_empty;
+    use crate::map::invariants::lint_building;
+    use crate::map::starter::mark_player_start_tile;
+    use crate::map::wall_wrap::wrap_walls;
+
+    const SPEC: MissionSpec = MissionSpec {
+        locks: 3,
+        panels: 1,
+        rooms: 3,
+    };
+
+    #[test]
+    fn grammar_keeps_switches_before_locks() {
+        for seed in 0..20 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let mission = MissionGraph::generate(&SPEC, &mut rng);
+            // locks can add a room in front of the goal when the way there is already locked
+            assert!(mission.rooms.len() >= 2 + 3 + 3);
+            assert_eq!(mission.edges.len(), mission.rooms.len() - 1);
+            assert_eq!(mission.locks().count(), 3);
+            assert_eq!(mission.locks().filter(|l| l.panel.is_some()).count(), 1);
+            assert!(mission.is_solvable(), "seed {seed}");
+
+            // the goal is behind every lock
+            let goal_path = mission.path_to(mission.goal());
+            let locked = goal_path
+                .iter()
+                .filter(|r| mission.edge_into(**r).is_some_and(|e| e.lock.is_some()))
+                .count();
+            assert_eq!(locked, 3);
+        }
+    }
+
+    #[test]
+    fn capped_spec() {
+        let spec = MissionSpec {
+            locks: u8::MAX,
+            panels: u8::MAX,
+            rooms: 0,
+        };
+        assert_eq!(spec.capped().locks, MAX_LOCKS);
+        assert_eq!(spec.capped().panels, MAX_LOCKS);
+
+        // every lock still gets its own ids
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mission = MissionGraph::generate(&spec, &mut rng);
+        assert_eq!(mission.locks().count(), MAX_LOCKS as usize);
+        assert!(mission.locks().all(|l| l.panel.is_some()));
+    }
+
+    #[test]
+    fn switch_behind_its_own_door() {
+        let mut mission = MissionGraph::new();
+        mission.edges[0].lock = Some(MissionLock {
+            door: 1,
+            switch: 2,
+            panel: None,
+            key_room: 1,
+        });
+        assert!(!mission.is_solvable());
+        let links = mission.links();
+        assert_eq!((links[0].source, links[0].target), (2, 1));
+    }
+
+    #[test]
+    fn embeds_into_maze() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let mission = MissionGraph::generate(&SPEC, &mut rng);
+        let layout = mission.embed(4, 3, &mut rng).unwrap();
+
+        // every node is used and the maze is still a tree
+        assert_eq!(layout.owners.len(), 12);
+        assert_eq!(layout.maze.edges.len(), 11);
+        for edge in mission.edges.iter() {
+            let (a, b) = (layout.nodes[edge.from], layout.nodes[edge.to]);
+            assert!(layout.maze.edges.contains(&(a, b)));
+            assert_eq!(layout.owners[&b], edge.to);
+        }
+
+        assert!(mission.embed(2, 2, &mut rng).is_none());
+    }
+
+    #[test]
+    fn mission_puzzles_are_solvable() {
+        for seed in 0..5 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let puzzle = generate_mission_puzzle(48, 30, &SPEC, &mut rng);
+            assert_eq!(puzzle.starting_links.len(), 3, "seed {seed}");
+            let start = puzzle.player_start().unwrap();
+            let report = solve(&puzzle, &start, &SolveGoal::Everything);
+            let exit = puzzle.exit().unwrap();
+            assert!(solve(&puzzle, &start, &SolveGoal::Reach(exit)).is_solvable());
+            // a switch that's already on can be wired into a panel instead of flipping its own
+            let metrics = report.metrics.unwrap();
+            assert!(metrics.toggles + metrics.rewires >= 3, "seed {seed}");
+
+            let mut grid = wrap_walls(puzzle.grid, &mut rng);
+            decorate_empty(&mut grid, &mut rng);
+            mark_player_start_tile(&mut grid, 1, &mut rng);
+            let report = lint_building(&grid);
+            assert!(report.is_ok(), "seed {seed}: {report}");
+        }
+    }
+}
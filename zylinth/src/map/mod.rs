@@ -1,9 +1,17 @@
+mod biome;
+mod colliders;
 mod decoration;
 pub mod functional_tiles;
+mod invariants;
+mod level_asset;
 mod lighting;
 mod maze;
+mod maze_rooms;
+mod mission;
 mod plugin;
 mod puzzle;
+mod puzzle_gen;
+mod puzzle_solver;
 mod room;
 mod spawn_building;
 mod special;
@@ -14,9 +22,29 @@ mod tuesday;
 mod walking_squares;
 mod wall_wrap;
 
+// layers of a generated puzzle, before walls are wrapped around it
+const BASE_LAYER: usize = 0;
+const INTERACTABLES_LAYER: usize = 1;
+// the same layers once walls are wrapped and decorations inserted underneath
+const WRAPPED_BASE_LAYER: usize = 1;
+const WRAPPED_INTERACTABLES_LAYER: usize = 2;
+/// Layouts thrown away before a generator gives up
+const MAX_ATTEMPTS: usize = 10;
+
+pub use lighting::default_ambient;
 pub use plugin::*;
-pub use spawn_building::SpawnBuildingMap;
+pub use spawn_building::{BuildingLayout, SpawnBuildingMap};
+pub use tileset::{Tileset, tilesets_ready};
 pub use tuesday::TuesdayTile;
 
+pub use level_asset::SpawnLevelFromAsset;
+#[allow(unused)]
+pub use maze::MazeAlgorithm;
+#[allow(unused)]
+pub use mission::MissionSpec;
+#[allow(unused)]
+pub use puzzle_gen::PuzzleSpec;
 #[allow(unused)]
 pub use tilemap::*;
+#[allow(unused)]
+pub use walking_squares::{WalkBias, WalkOptions};
@@ -1,10 +1,11 @@
-use crate::defs::GameLayer;
-use crate::map::lighting::spot_lights;
-use crate::map::tilemap::{RenderedTileLayer, render_tilemap};
+use crate::map::level_asset::{LevelAsset, LevelAssetLoader, spawn_pending_levels};
+use crate::map::lighting::{animate_lights, room_ambient, spot_lights};
+use crate::map::tilemap::{RenderedTileLayer, SetTileCollider, update_tile_colliders};
 use crate::map::tileset::*;
 
 use super::tileset::Tileset;
 use bevy::prelude::*;
+use serde::Deserialize;
 
 pub struct TileLayoutPlugin;
 
@@ -12,32 +13,117 @@ impl Plugin for TileLayoutPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<RenderedTileLayer>();
         app.add_event::<NewMap>();
+        app.add_event::<SetTileCollider>();
 
         app.init_asset::<Tileset>();
+        app.init_resource::<Tilesets>();
+        app.init_asset_loader::<TilesetLoader>();
+        app.init_asset::<LevelAsset>();
+        app.init_asset_loader::<LevelAssetLoader>();
 
-        app.add_systems(PreStartup, init_tuesday_tileset);
+        app.add_systems(PreStartup, load_tilesets);
         app.add_systems(
             PostUpdate,
             spot_lights.after(TransformSystem::TransformPropagate),
         );
 
-        app.add_observer(render_tilemap);
+        app.add_systems(Update, spawn_pending_levels.run_if(tilesets_ready));
+        app.add_systems(Update, (animate_lights, room_ambient));
+        app.add_systems(Update, update_tile_colliders);
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TileSprite {
     pub index: usize,
     pub collider: bool,
     pub role: Option<TileRole>,
+    pub light: Option<TileLight>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TileRole {
     Switch(u8, bool),
-    Door(u8),
+    /// Door id, whether it's open until it's powered and how it opens
+    Door(u8, bool, DoorKind),
     DoorPanel(u8),
     PlayerStart(u8),
+    /// Stepping on this finishes the level
+    Exit,
+    /// Picking this up lets the player through the keycard door with the same id
+    Keycard(u8),
+    /// Gate id and how it combines everything wired into it
+    Gate(u8, GateKind),
+}
+
+/// What it takes to open a door
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum DoorKind {
+    /// Open while it's powered
+    #[default]
+    Standard,
+    /// Closes again this long after it opens, until it's switched off and on
+    Timed { seconds: u8 },
+    /// Only opens for the player coming towards it heading this way
+    OneWay(Heading),
+    /// Opens for the player when they're carrying its keycard, whether it's powered or not
+    Keycard,
+    /// Only powered while every link into it is
+    AllLinks,
+}
+
+/// How a gate turns the sources wired into it into its own output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum GateKind {
+    /// On while every input is on
+    And,
+    /// On while any input is on
+    Or,
+    /// On while an odd number of inputs are on
+    Xor,
+    /// On while no input is on, which inverts a single input
+    Not,
+    /// Its selector picks which of two inputs to pass along, the first while it's off and the
+    /// second while it's on. The selector is wired into on its own, and comes first in the inputs
+    Mux,
+}
+
+impl GateKind {
+    /// The gate's output, given whether each input is on in order of source id, after a
+    /// multiplexer's selector
+    pub fn output(&self, inputs: &[bool]) -> bool {
+        match self {
+            GateKind::And => !inputs.is_empty() && inputs.iter().all(|on| *on),
+            GateKind::Or => inputs.iter().any(|on| *on),
+            GateKind::Xor => inputs.iter().filter(|on| **on).count() % 2 == 1,
+            GateKind::Not => !inputs.iter().any(|on| *on),
+            GateKind::Mux => match inputs.split_first() {
+                Some((select, rest)) => rest.get(*select as usize).copied().unwrap_or(false),
+                None => false,
+            },
+        }
+    }
+}
+
+/// A direction across the map, with up being towards the top of the grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Heading {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Heading {
+    /// The direction in world space, where y points up
+    pub fn world(&self) -> Vec2 {
+        match self {
+            Heading::Up => Vec2::Y,
+            Heading::Down => Vec2::NEG_Y,
+            Heading::Left => Vec2::NEG_X,
+            Heading::Right => Vec2::X,
+        }
+    }
 }
 
 pub trait IsImpassable {
@@ -53,14 +139,5 @@ pub enum TileLayerRole {
     Interactables,
 }
 
-#[allow(unused)]
-#[derive(Component, Default)]
-pub struct TileLayer {
-    pub layer: GameLayer,
-    pub grid: Vec<Vec<Option<TileSprite>>>,
-    pub tileset_name: &'static str,
-    pub z: f32,
-}
-
 #[derive(Event)]
 pub struct NewMap;
@@ -1,8 +1,33 @@
-use tilegen::TileGrid;
+use tilegen::{TileGrid, TilePoint};
 
+use super::tuesday::TuesdayTile;
 use crate::defs::ControlLink;
 
 pub struct Puzzle<T: Clone + PartialEq + Eq> {
     pub grid: TileGrid<T>,
     pub starting_links: Vec<ControlLink>,
 }
+
+impl Puzzle<TuesdayTile> {
+    /// Find where the player starts, if it has been marked
+    pub fn player_start(&self) -> Option<TilePoint> {
+        self.find(|t| matches!(t, TuesdayTile::PlayerStart(_)))
+    }
+
+    /// Find the exit, if it has been marked
+    #[allow(unused)]
+    pub fn exit(&self) -> Option<TilePoint> {
+        self.find(|t| *t == TuesdayTile::Exit)
+    }
+
+    fn find(&self, matches: impl Fn(&TuesdayTile) -> bool) -> Option<TilePoint> {
+        for x in 0..self.grid.width() {
+            for y in 0..self.grid.height() {
+                if self.grid[x][y].iter().flatten().any(&matches) {
+                    return Some(TilePoint::new(x, y));
+                }
+            }
+        }
+        None
+    }
+}
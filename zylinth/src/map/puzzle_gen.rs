@@ -0,0 +1,620 @@
+use std::collections::{HashMap, VecDeque};
+
+use bevy::prelude::*;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use tilegen::{TileDir, TileGrid, TilePoint, TileRegion};
+
+use super::maze::{Maze, MazeAlgorithm};
+use super::maze_rooms::{MAZE_CELL_HEIGHT, MAZE_CELL_WIDTH, maze_dimensions, maze_to_rooms};
+use super::puzzle::Puzzle;
+use super::puzzle_solver::{SolveGoal, solve};
+use super::tuesday::{TuesdayTile, TuesdayTile::*};
+use super::{BASE_LAYER, Heading, INTERACTABLES_LAYER, MAX_ATTEMPTS};
+use crate::defs::ControlLink;
+
+/// Hitting a difficulty band takes more luck than just being solvable
+const MAX_DIFFICULTY_ATTEMPTS: usize = 50;
+/// Most doors a puzzle can have. Each door can use up to 4 ids out of the 255 a `u8` has
+const MAX_DOORS: u8 = 63;
+
+/// What a generated switch/door puzzle should contain
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PuzzleSpec {
+    /// Locked doors, which have to be opened in order. The switch for each door is placed behind
+    /// the door before it
+    pub doors: u8,
+    /// Total switches. Each door needs one, or two for doors that need all their links on, and
+    /// any left over are placed unconnected as decoys
+    pub switches: u8,
+    /// How many of the doors are controlled by a panel next to them. The player has to wire the
+    /// switch to the panel themselves
+    pub panels: u8,
+    /// How many of the doors are timed, one way, keycard or two switch doors instead of plain ones
+    pub special_doors: u8,
+    /// Inclusive range the puzzle's difficulty score should fall in. See `PuzzleMetrics::difficulty`
+    pub difficulty: Option<(f32, f32)>,
+}
+
+#[allow(unused)]
+impl PuzzleSpec {
+    /// A spec for the nth level of a run, adding doors, decoys and panels as the levels go on so
+    /// the run gets harder from one level to the next
+    pub fn for_level(level: u8) -> Self {
+        let doors = 1 + level / 2;
+        let panels = (level / 3).min(doors);
+        let min = level as f32 * 1.5;
+        Self {
+            doors,
+            switches: doors + level / 4,
+            panels,
+            special_doors: level / 4,
+            difficulty: Some((min, min + 3.0)),
+        }
+        .capped()
+    }
+
+    /// The spec cut down to as many doors and switches as there are ids for
+    pub fn capped(&self) -> Self {
+        let doors = self.doors.min(MAX_DOORS);
+        // every door and panel takes an id, and the switches take the rest
+        let switches = self.switches.min(u8::MAX - 1 - doors * 2);
+        Self {
+            doors,
+            switches,
+            panels: self.panels.min(doors),
+            special_doors: self.special_doors.min(doors),
+            ..*self
+        }
+    }
+}
+
+/// Generate a maze building and lock its rooms behind a chain of switch controlled doors.
+/// Layouts which can't be solved, or don't land in the spec's difficulty band, are thrown away
+/// and generated again. If the band is never hit the closest solvable puzzle is used instead
+pub fn generate_puzzle(
+    width: usize,
+    height: usize,
+    algorithm: MazeAlgorithm,
+    spec: &PuzzleSpec,
+    rng: &mut ChaCha8Rng,
+) -> Puzzle<TuesdayTile> {
+    let spec = &spec.capped();
+    let max_attempts = match spec.difficulty {
+        Some(_) => MAX_DIFFICULTY_ATTEMPTS,
+        None => MAX_ATTEMPTS,
+    };
+    // closest solvable puzzle so far, and how far its difficulty was from the band
+    let mut closest: Option<(Puzzle<TuesdayTile>, f32)> = None;
+
+    for attempt in 1..=max_attempts {
+        let puzzle = build_puzzle(width, height, algorithm, spec, rng);
+        let metrics = puzzle
+            .player_start()
+            .and_then(|start| solve(&puzzle, &start, &SolveGoal::Everything).metrics);
+        let Some(metrics) = metrics else {
+            debug!("Generated puzzle {attempt} was not solvable, trying again");
+            if closest.is_none() && attempt == max_attempts {
+                warn!("Could not generate a solvable puzzle after {attempt} attempts");
+                return puzzle;
+            }
+            continue;
+        };
+
+        let difficulty = metrics.difficulty();
+        let miss = match spec.difficulty {
+            Some((min, max)) => (min - difficulty).max(difficulty - max).max(0.0),
+            None => 0.0,
+        };
+        if miss == 0.0 {
+            return puzzle;
+        }
+        debug!("Generated puzzle {attempt} has difficulty {difficulty}, trying again");
+        if closest.as_ref().is_none_or(|(_, closest)| miss < *closest) {
+            closest = Some((puzzle, miss));
+        }
+    }
+
+    warn!("Could not generate a puzzle in the difficulty band after {max_attempts} attempts");
+    closest.map(|(puzzle, _)| puzzle).unwrap()
+}
+
+fn build_puzzle(
+    width: usize,
+    height: usize,
+    algorithm: MazeAlgorithm,
+    spec: &PuzzleSpec,
+    rng: &mut ChaCha8Rng,
+) -> Puzzle<TuesdayTile> {
+    let (maze_width, maze_height) = maze_dimensions(width, height);
+    let maze = Maze::generate_with(maze_width, maze_height, algorithm, rng);
+    let (mut grid, rooms) = maze_to_rooms(&maze, MAZE_CELL_WIDTH, MAZE_CELL_HEIGHT, Floor, rng);
+    grid.push_layer();
+
+    // doors are spread along the longest route through the maze so every one of them has to be opened
+    let path = maze.longest_path();
+    let door_count = (spec.doors as usize).min(path.len().saturating_sub(1));
+    if door_count < spec.doors as usize {
+        warn!(
+            "Building only has room for {door_count} of {} doors",
+            spec.doors
+        );
+    }
+    let mut door_edges = (0..path.len().saturating_sub(1)).choose_multiple(rng, door_count);
+    door_edges.sort();
+    let door_edges = door_edges
+        .into_iter()
+        .map(|i| (path[i], path[i + 1]))
+        .collect::<Vec<_>>();
+    let zones = zone_rooms(&maze, path[0], &door_edges);
+
+    let start = place_player_start(&mut grid, &rooms[path[0] as usize], rng);
+    let mut starting_links = vec![];
+    let mut next_id: u8 = 1;
+    let mut switches_placed = 0;
+    let special = match spec.special_doors {
+        0 => vec![],
+        count => (0..door_count).choose_multiple(rng, count as usize),
+    };
+
+    for (zone, (from, to)) in door_edges.iter().enumerate() {
+        let (from, to) = (&rooms[*from as usize], &rooms[*to as usize]);
+        let (door, blocker) = doorway(&maze, from, to);
+        let door_id = next_id;
+        next_id += 1;
+        let frame = if special.contains(&zone) {
+            let heading = match doorway_dir(&maze, from, to) {
+                TileDir::Up => Heading::Up,
+                TileDir::Down => Heading::Down,
+                TileDir::Left => Heading::Left,
+                TileDir::Right => Heading::Right,
+            };
+            *[
+                DoorFrameTimed(door_id),
+                DoorFrameOneWay(door_id, heading),
+                DoorFrameKeycard(door_id),
+                DoorFrameAll(door_id),
+            ]
+            .choose(rng)
+            .unwrap()
+        } else {
+            DoorFrame(door_id)
+        };
+        grid[door.x][door.y][INTERACTABLES_LAYER] = Some(frame);
+        // narrow the corridor mouth down to just the door
+        grid[blocker.x][blocker.y][BASE_LAYER] = None;
+
+        if frame == DoorFrameKeycard(door_id) {
+            // the keycard is found where the switch would have been
+            place_tile(
+                &mut grid,
+                &rooms,
+                &zones,
+                zone,
+                Keycard(door_id),
+                &start,
+                rng,
+            );
+            continue;
+        }
+
+        let switch_id = next_id;
+        next_id += 1;
+        switches_placed += 1;
+        // timed doors only stay open for a few seconds, so their switch goes in the room the door
+        // leads out of where the player can make it through in time
+        let timed = frame == DoorFrameTimed(door_id);
+        if !timed || !place_in_rooms(&mut grid, &[from], SwitchLeft(switch_id), &start, rng) {
+            place_tile(
+                &mut grid,
+                &rooms,
+                &zones,
+                zone,
+                SwitchLeft(switch_id),
+                &start,
+                rng,
+            );
+        }
+
+        if zone < spec.panels as usize {
+            let panel_id = next_id;
+            next_id += 1;
+            grid[blocker.x][blocker.y][INTERACTABLES_LAYER] = Some(PanelDisabled(panel_id));
+            starting_links.push(ControlLink::new(panel_id, door_id));
+        } else {
+            starting_links.push(ControlLink::new(switch_id, door_id));
+        }
+
+        if frame == DoorFrameAll(door_id) {
+            // a second switch that has to be on as well
+            let extra_id = next_id;
+            next_id += 1;
+            switches_placed += 1;
+            place_tile(
+                &mut grid,
+                &rooms,
+                &zones,
+                zone,
+                SwitchLeft(extra_id),
+                &start,
+                rng,
+            );
+            starting_links.push(ControlLink::new(extra_id, door_id));
+        }
+    }
+
+    for _ in switches_placed..(spec.switches as usize) {
+        let zone = (0..=door_count).choose(rng).unwrap();
+        place_tile(
+            &mut grid,
+            &rooms,
+            &zones,
+            zone,
+            SwitchLeft(next_id),
+            &start,
+            rng,
+        );
+        next_id += 1;
+    }
+
+    // the exit is behind the last door
+    place_tile(&mut grid, &rooms, &zones, door_count, Exit, &start, rng);
+
+    Puzzle {
+        grid,
+        starting_links,
+    }
+}
+
+/// Number each room by how many doors have to be opened to reach it from the start
+fn zone_rooms(maze: &Maze, start: u32, door_edges: &[(u32, u32)]) -> HashMap<u32, usize> {
+    let mut zones = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        for next in maze.neighbors(node) {
+            if zones.contains_key(&next) {
+                continue;
+            }
+            let crossed = door_edges
+                .iter()
+                .position(|(a, b)| (*a, *b) == (node, next) || (*a, *b) == (next, node));
+            let zone = match crossed {
+                Some(door) => door + 1,
+                None => zones[&node],
+            };
+            zones.insert(next, zone);
+            queue.push_back(next);
+        }
+    }
+    zones
+}
+
+/// Get the tile for the door and the tile next to it in the mouth of the corridor leading from one room to another
+pub fn doorway(
+    maze: &Maze,
+    from: &TileRegion<u32>,
+    to: &TileRegion<u32>,
+) -> (TilePoint, TilePoint) {
+    let dir = doorway_dir(maze, from, to);
+    let exit = from.exits.get(dir).first().unwrap();
+    let (x, y) = (exit.from.x, exit.from.y);
+    match dir {
+        TileDir::Right => (TilePoint::new(x, y), TilePoint::new(x, y + 1)),
+        TileDir::Left => (TilePoint::new(x - 1, y), TilePoint::new(x - 1, y + 1)),
+        TileDir::Down => (TilePoint::new(x, y), TilePoint::new(x + 1, y)),
+        TileDir::Up => (TilePoint::new(x, y - 1), TilePoint::new(x + 1, y - 1)),
+    }
+}
+
+/// Which way the player heads going from one room to the next
+fn doorway_dir(maze: &Maze, from: &TileRegion<u32>, to: &TileRegion<u32>) -> TileDir {
+    // rooms are placed randomly within their cells, so go by where the nodes are in the maze
+    let (a, b) = (from.region_type, to.region_type);
+    let width = maze.width as u32;
+    if b == a + 1 && a / width == b / width {
+        TileDir::Right
+    } else if a == b + 1 && a / width == b / width {
+        TileDir::Left
+    } else if b > a {
+        TileDir::Down
+    } else {
+        TileDir::Up
+    }
+}
+
+/// Mark a spot in the room with enough floor for the player, returning its top left
+pub fn place_player_start(
+    grid: &mut TileGrid<TuesdayTile>,
+    room: &TileRegion<u32>,
+    rng: &mut ChaCha8Rng,
+) -> TilePoint {
+    let x = (room.min.x..room.max.x - 1).choose(rng).unwrap();
+    let y = (room.min.y..room.max.y - 1).choose(rng).unwrap();
+    grid[x][y][INTERACTABLES_LAYER] = Some(PlayerStart(1));
+    TilePoint::new(x, y)
+}
+
+/// Put a switch, or anything else the player interacts with, somewhere in the given zone
+pub fn place_tile(
+    grid: &mut TileGrid<TuesdayTile>,
+    rooms: &[TileRegion<u32>],
+    zones: &HashMap<u32, usize>,
+    zone: usize,
+    tile: TuesdayTile,
+    start: &TilePoint,
+    rng: &mut ChaCha8Rng,
+) {
+    let rooms = rooms
+        .iter()
+        .filter(|room| zones.get(&room.region_type) == Some(&zone))
+        .collect::<Vec<_>>();
+    if !place_in_rooms(grid, &rooms, tile, start, rng) {
+        warn!("No space left for {tile:?} in zone {zone}");
+    }
+}
+
+/// Put a tile somewhere free in one of the rooms, returning whether there was space for it
+fn place_in_rooms(
+    grid: &mut TileGrid<TuesdayTile>,
+    rooms: &[&TileRegion<u32>],
+    tile: TuesdayTile,
+    start: &TilePoint,
+    rng: &mut ChaCha8Rng,
+) -> bool {
+    // prefer spots away from the edges of rooms so a switch can't block a doorway
+    for padding in [1, 0] {
+        let spots = rooms
+            .iter()
+            .flat_map(|room| {
+                (room.min.x + padding..room.max.x - padding).flat_map(move |x| {
+                    (room.min.y + padding..room.max.y - padding).map(move |y| (x, y))
+                })
+            })
+            .filter(|(x, y)| {
+                let on_start =
+                    (start.x..start.x + 2).contains(x) && (start.y..start.y + 2).contains(y);
+                !on_start && grid[*x][*y][INTERACTABLES_LAYER].is_none()
+            })
+            .collect::<Vec<_>>();
+
+        if let Some((x, y)) = spots.choose(rng) {
+            grid[*x][*y][INTERACTABLES_LAYER] = Some(tile);
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::decoration::decorate_empty;
+    use crate::map::invariants::lint_building;
+    use crate::map::starter::mark_player_start_tile;
+    use crate::map::wall_wrap::wrap_walls;
+
+    fn find(grid: &TileGrid<TuesdayTile>, tile: TuesdayTile) -> TilePoint {
+        for x in 0..grid.width() {
+            for y in 0..grid.height() {
+                if grid[x][y][INTERACTABLES_LAYER] == Some(tile) {
+                    return TilePoint::new(x, y);
+                }
+            }
+        }
+        panic!("{:?} not found", tile);
+    }
+
+    /// Walk the floor from the start without going through any of the closed doors
+    fn reachable(grid: &TileGrid<TuesdayTile>, open: &[u8]) -> Vec<Vec<bool>> {
+        let start = find(grid, PlayerStart(1));
+        let mut seen = vec![vec![false; grid.height()]; grid.width()];
+        let mut stack = vec![(start.x, start.y)];
+        while let Some((x, y)) = stack.pop() {
+            if x >= grid.width()
+                || y >= grid.height()
+                || seen[x][y]
+                || grid[x][y][BASE_LAYER].is_none()
+            {
+                continue;
+            }
+            if matches!(grid[x][y][INTERACTABLES_LAYER], Some(DoorFrame(id)) if !open.contains(&id))
+            {
+                continue;
+            }
+            seen[x][y] = true;
+            stack.extend([
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ]);
+        }
+        seen
+    }
+
+    #[test]
+    fn doors_gate_switches() {
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let spec = PuzzleSpec {
+            doors: 2,
+            switches: 2,
+            panels: 0,
+            special_doors: 0,
+            difficulty: None,
+        };
+        let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Wilson, &spec, &mut rng);
+
+        // door 1 is opened by switch 2, door 3 by switch 4
+        let links = puzzle
+            .starting_links
+            .iter()
+            .map(|l| (l.source, l.target))
+            .collect::<Vec<_>>();
+        assert_eq!(links, vec![(2, 1), (4, 3)]);
+
+        let first = find(&puzzle.grid, SwitchLeft(2));
+        let second = find(&puzzle.grid, SwitchLeft(4));
+
+        let closed = reachable(&puzzle.grid, &[]);
+        assert!(closed[first.x][first.y]);
+        assert!(!closed[second.x][second.y]);
+
+        let first_open = reachable(&puzzle.grid, &[1]);
+        assert!(first_open[second.x][second.y]);
+
+        // the exit is behind the last door
+        let exit = puzzle.exit().unwrap();
+        assert!(!first_open[exit.x][exit.y]);
+        let both_open = reachable(&puzzle.grid, &[1, 3]);
+        assert!(both_open[exit.x][exit.y]);
+    }
+
+    #[test]
+    fn panels_guard_doors() {
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let spec = PuzzleSpec {
+            doors: 2,
+            switches: 3,
+            panels: 1,
+            special_doors: 0,
+            difficulty: None,
+        };
+        let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Kruskal, &spec, &mut rng);
+
+        // the first door is wired to its panel, which sits right next to it
+        assert_eq!(puzzle.starting_links[0].source, 3);
+        assert_eq!(puzzle.starting_links[0].target, 1);
+        let door = find(&puzzle.grid, DoorFrame(1));
+        let panel = find(&puzzle.grid, PanelDisabled(3));
+        assert_eq!(door.x.abs_diff(panel.x) + door.y.abs_diff(panel.y), 1);
+
+        // the second door is wired straight to its switch
+        assert_eq!(puzzle.starting_links[1].source, 5);
+        assert_eq!(puzzle.starting_links[1].target, 4);
+
+        // plus one decoy
+        find(&puzzle.grid, SwitchLeft(6));
+    }
+
+    #[test]
+    fn special_doors() {
+        let spec = PuzzleSpec {
+            doors: 3,
+            switches: 3,
+            panels: 0,
+            special_doors: 3,
+            difficulty: None,
+        };
+        let mut timed = 0;
+        for seed in 0..10 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Wilson, &spec, &mut rng);
+            let start = puzzle.player_start().unwrap();
+            let report = solve(&puzzle, &start, &SolveGoal::Everything);
+            assert!(report.is_solvable(), "seed {seed}");
+
+            // decoys only make up what the doors didn't already use
+            let tiles = puzzle.grid.iter().flatten().flatten().flatten();
+            let switches = tiles.clone().filter(|t| matches!(t, SwitchLeft(_))).count();
+            let wired = puzzle.starting_links.len();
+            assert_eq!(switches, wired.max(spec.switches as usize), "seed {seed}");
+
+            let doors = tiles.filter_map(|t| t.door_id().map(|id| (id, *t)));
+            for (id, door) in doors {
+                assert_ne!(door, DoorFrame(id), "seed {seed}");
+                let links = puzzle.starting_links.iter().filter(|l| l.target == id);
+                if door == DoorFrameTimed(id) {
+                    // the switch is close enough to make it to the door in time
+                    let link = links.clone().next().unwrap();
+                    let switch = find(&puzzle.grid, SwitchLeft(link.source));
+                    let door = find(&puzzle.grid, door);
+                    let distance = switch.x.abs_diff(door.x) + switch.y.abs_diff(door.y);
+                    assert!(
+                        distance <= MAZE_CELL_WIDTH + MAZE_CELL_HEIGHT,
+                        "seed {seed}"
+                    );
+                    timed += 1;
+                }
+                let expected = match door {
+                    DoorFrameKeycard(_) => {
+                        find(&puzzle.grid, Keycard(id));
+                        0
+                    }
+                    DoorFrameAll(_) => 2,
+                    _ => 1,
+                };
+                assert_eq!(links.count(), expected, "seed {seed}: {door:?}");
+            }
+        }
+        assert!(timed > 0);
+    }
+
+    #[test]
+    fn capped_spec() {
+        let spec = PuzzleSpec::for_level(u8::MAX);
+        assert_eq!(spec.doors, MAX_DOORS);
+        // a door, panel and two switches for every door still fit
+        let ids = spec.doors as usize * 2 + (spec.switches as usize).max(spec.doors as usize * 2);
+        assert!(ids < u8::MAX as usize);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let spec = PuzzleSpec {
+            doors: MAX_DOORS,
+            switches: u8::MAX,
+            panels: MAX_DOORS,
+            special_doors: MAX_DOORS,
+            difficulty: None,
+        };
+        // too big to solve, but building it must not run out of ids
+        let spec = spec.capped();
+        build_puzzle(400, 400, MazeAlgorithm::Wilson, &spec, &mut rng);
+    }
+
+    #[test]
+    fn difficulty_band() {
+        for level in 0..8 {
+            let spec = PuzzleSpec::for_level(level);
+            let mut rng = ChaCha8Rng::seed_from_u64(level as u64);
+            let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Wilson, &spec, &mut rng);
+            let start = puzzle.player_start().unwrap();
+            let metrics = solve(&puzzle, &start, &SolveGoal::Everything)
+                .metrics
+                .unwrap();
+            let (min, max) = spec.difficulty.unwrap();
+            let difficulty = metrics.difficulty();
+            assert!(
+                min <= difficulty && difficulty <= max,
+                "level {level}: {difficulty}"
+            );
+        }
+    }
+
+    #[test]
+    fn puzzle_invariants() {
+        let spec = PuzzleSpec {
+            doors: 3,
+            switches: 4,
+            panels: 2,
+            special_doors: 0,
+            difficulty: None,
+        };
+        for seed in 0..10 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Prim, &spec, &mut rng);
+            let mut grid = wrap_walls(puzzle.grid, &mut rng);
+            decorate_empty(&mut grid, &mut rng);
+            mark_player_start_tile(&mut grid, 1, &mut rng);
+
+            let report = lint_building(&grid);
+            assert!(report.is_ok(), "seed {seed}: {report}");
+            let starts = grid
+                .iter()
+                .flatten()
+                .flatten()
+                .filter(|t| matches!(t, Some(PlayerStart(_))))
+                .count();
+            assert_eq!(starts, 1);
+        }
+    }
+}
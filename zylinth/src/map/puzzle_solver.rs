@@ -0,0 +1,806 @@
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use tilegen::{TileGrid, TilePoint};
+
+use super::puzzle::Puzzle;
+use super::tuesday::{TuesdayTile, TuesdayTile::*};
+use super::{BASE_LAYER, GateKind, Heading, INTERACTABLES_LAYER, IsImpassable};
+
+/// Give up on puzzles with more possible states than this
+const MAX_STATES: usize = 50_000;
+
+/// Something the player can do to change the state of a puzzle
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PuzzleAction {
+    /// Flip a switch
+    Toggle(u8),
+    /// Wire a switch, panel or gate into a panel, replacing whatever was wired into it before, or
+    /// into a gate as another input
+    Connect { source: u8, target: u8 },
+    /// Wire a switch, panel or gate into a multiplexer's selector, replacing whatever was wired
+    /// into it before
+    Select { source: u8, gate: u8 },
+}
+
+/// What counts as solving a puzzle
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SolveGoal {
+    /// Reach a specific tile, like the exit
+    Reach(TilePoint),
+    /// Reach every floor tile in the puzzle
+    Everything,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleReport {
+    /// Fewest actions that reach the goal, or nothing if it can't be reached
+    pub solution: Option<Vec<PuzzleAction>>,
+    /// How hard the shortest solution is, if there is one
+    pub metrics: Option<PuzzleMetrics>,
+    /// Switches, panels, doors, keycards and gates which can't be reached no matter what the player does
+    pub unreachable: Vec<TilePoint>,
+    /// How many distinct switch and wiring states were explored
+    pub states: usize,
+    /// The state space was too large to fully explore
+    pub truncated: bool,
+}
+
+#[allow(unused)]
+impl PuzzleReport {
+    pub fn is_solvable(&self) -> bool {
+        self.solution.is_some()
+    }
+}
+
+/// What the player has to go through to solve a puzzle
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PuzzleMetrics {
+    /// Switch flips in the shortest solution
+    pub toggles: usize,
+    /// Wires that have to be moved in the shortest solution
+    pub rewires: usize,
+    /// Tiles walked going back and forth between switches and panels while following the shortest solution
+    pub backtracking: usize,
+    /// Reachable states the goal can't be reached from anymore
+    pub dead_ends: usize,
+}
+
+#[allow(unused)]
+impl PuzzleMetrics {
+    /// A single number to rank puzzles by. Rewiring is weighted above flipping switches since it
+    /// means working out the wiring, and getting stuck is weighted above walking
+    pub fn difficulty(&self) -> f32 {
+        self.toggles as f32
+            + self.rewires as f32 * 2.0
+            + self.backtracking as f32 / 20.0
+            + (self.dead_ends as f32).ln_1p()
+    }
+}
+
+/// Switch positions and wiring at a point in time. Player position isn't needed since the player
+/// can walk anywhere that is reachable between actions
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct PuzzleState {
+    switches: Vec<bool>,
+    /// target id -> source id
+    links: BTreeMap<u8, u8>,
+    /// Every input wired into a gate, as gate id and source id
+    gate_links: BTreeSet<(u8, u8)>,
+    /// multiplexer id -> source id wired into its selector
+    selectors: BTreeMap<u8, u8>,
+}
+
+struct Interactables {
+    switches: Vec<(u8, TilePoint)>,
+    panels: Vec<(u8, TilePoint)>,
+    doors: Vec<(u8, TilePoint)>,
+    keycards: Vec<(u8, TilePoint)>,
+    gates: Vec<(u8, GateKind, TilePoint)>,
+    /// Wires into a door on top of the first one, as target and source. They can't be rewired
+    extra_links: Vec<(u8, u8)>,
+}
+
+impl Interactables {
+    /// Where a switch or panel is
+    fn position(&self, id: u8) -> Option<TilePoint> {
+        self.switches
+            .iter()
+            .chain(self.panels.iter())
+            .find(|(i, _)| *i == id)
+            .map(|(_, p)| *p)
+            .or_else(|| self.gates.iter().find(|(i, ..)| *i == id).map(|g| g.2))
+    }
+}
+
+/// Explore every state the player can put the puzzle into, starting from the given tile.
+/// Closed doors and switches are treated as walls, and switches and panels can be used from any
+/// tile next to them. Wiring into a gate adds to its inputs rather than replacing them, the same as
+/// it does in game
+#[allow(unused)]
+pub fn solve(puzzle: &Puzzle<TuesdayTile>, start: &TilePoint, goal: &SolveGoal) -> PuzzleReport {
+    let grid = &puzzle.grid;
+    let mut interactables = Interactables {
+        switches: vec![],
+        panels: vec![],
+        doors: vec![],
+        keycards: vec![],
+        gates: vec![],
+        extra_links: vec![],
+    };
+    let mut initial = PuzzleState {
+        switches: vec![],
+        links: BTreeMap::new(),
+        gate_links: BTreeSet::new(),
+        selectors: BTreeMap::new(),
+    };
+    for x in 0..grid.width() {
+        for y in 0..grid.height() {
+            let point = TilePoint::new(x, y);
+            match grid[x][y][INTERACTABLES_LAYER] {
+                Some(SwitchLeft(id)) | Some(SwitchRight(id)) => {
+                    interactables.switches.push((id, point));
+                    initial.switches.push(matches!(
+                        grid[x][y][INTERACTABLES_LAYER],
+                        Some(SwitchRight(_))
+                    ));
+                }
+                Some(PanelDisabled(id)) | Some(PanelEnabled(id)) => {
+                    interactables.panels.push((id, point))
+                }
+                Some(Keycard(id)) => interactables.keycards.push((id, point)),
+                Some(tile) if tile.gate().is_some() => {
+                    let (id, kind) = tile.gate().unwrap();
+                    interactables.gates.push((id, kind, point))
+                }
+                Some(tile) if tile.door_id().is_some() => {
+                    interactables.doors.push((tile.door_id().unwrap(), point))
+                }
+                _ => {}
+            }
+        }
+    }
+    for link in puzzle.starting_links.iter() {
+        if link.select {
+            initial.selectors.insert(link.target, link.source);
+            continue;
+        }
+        if interactables
+            .gates
+            .iter()
+            .any(|(id, ..)| *id == link.target)
+        {
+            initial.gate_links.insert((link.target, link.source));
+            continue;
+        }
+        match initial.links.entry(link.target) {
+            Entry::Occupied(_) => interactables.extra_links.push((link.target, link.source)),
+            Entry::Vacant(entry) => {
+                entry.insert(link.source);
+            }
+        }
+    }
+
+    let goal_tiles = match goal {
+        SolveGoal::Reach(point) => vec![*point],
+        SolveGoal::Everything => {
+            let mut tiles = vec![];
+            for x in 0..grid.width() {
+                for y in 0..grid.height() {
+                    if is_walkable(grid, x, y) {
+                        tiles.push(TilePoint::new(x, y));
+                    }
+                }
+            }
+            tiles
+        }
+    };
+
+    let mut ever_reached = vec![vec![false; grid.height()]; grid.width()];
+    let mut states = vec![initial.clone()];
+    let mut index: HashMap<PuzzleState, usize> = HashMap::from([(initial, 0)]);
+    let mut parents: Vec<Option<(usize, PuzzleAction)>> = vec![None];
+    let mut edges: Vec<Vec<usize>> = vec![];
+    let mut goal_met: Vec<bool> = vec![];
+    let mut truncated = false;
+
+    // states are added in the order they are queued, so walking through them is the BFS
+    let mut current = 0;
+    while current < states.len() {
+        let state = states[current].clone();
+        let reached = reachable(grid, &interactables, &state, start);
+        for x in 0..grid.width() {
+            for y in 0..grid.height() {
+                ever_reached[x][y] |= reached[x][y];
+            }
+        }
+        goal_met.push(goal_tiles.iter().all(|p| reached[p.x][p.y]));
+
+        let mut next_states = vec![];
+        for action in actions(&interactables, &state, &reached) {
+            let next = apply(&interactables, &state, action);
+            if let Some(i) = index.get(&next) {
+                next_states.push(*i);
+                continue;
+            }
+            if states.len() >= MAX_STATES {
+                truncated = true;
+                continue;
+            }
+            index.insert(next.clone(), states.len());
+            next_states.push(states.len());
+            parents.push(Some((current, action)));
+            states.push(next);
+        }
+        edges.push(next_states);
+        current += 1;
+    }
+
+    let solved = goal_met.iter().position(|met| *met);
+    let solution = solved.map(|mut i| {
+        let mut actions = vec![];
+        while let Some((previous, action)) = parents[i] {
+            actions.push(action);
+            i = previous;
+        }
+        actions.reverse();
+        actions
+    });
+
+    let metrics = solution.as_ref().map(|solution| PuzzleMetrics {
+        toggles: solution
+            .iter()
+            .filter(|a| matches!(a, PuzzleAction::Toggle(_)))
+            .count(),
+        rewires: solution
+            .iter()
+            .filter(|a| {
+                matches!(
+                    a,
+                    PuzzleAction::Connect { .. } | PuzzleAction::Select { .. }
+                )
+            })
+            .count(),
+        backtracking: backtracking(grid, &interactables, &states[0], solution, start),
+        dead_ends: dead_ends(&edges, &goal_met),
+    });
+
+    let unreachable = interactables
+        .switches
+        .iter()
+        .chain(interactables.panels.iter())
+        .chain(interactables.doors.iter())
+        .chain(interactables.keycards.iter())
+        .map(|(_, p)| *p)
+        .chain(interactables.gates.iter().map(|(.., p)| *p))
+        .filter(|p| !can_use(&ever_reached, p))
+        .collect();
+
+    PuzzleReport {
+        solution,
+        metrics,
+        unreachable,
+        states: states.len(),
+        truncated,
+    }
+}
+
+/// Count the states from which no sequence of actions leads to the goal
+fn dead_ends(edges: &[Vec<usize>], goal_met: &[bool]) -> usize {
+    let mut reverse = vec![vec![]; edges.len()];
+    for (from, next) in edges.iter().enumerate() {
+        for to in next {
+            reverse[*to].push(from);
+        }
+    }
+
+    let mut can_finish = goal_met.to_vec();
+    let mut stack = (0..goal_met.len())
+        .filter(|i| goal_met[*i])
+        .collect::<Vec<_>>();
+    while let Some(i) = stack.pop() {
+        for previous in reverse[i].iter() {
+            if !can_finish[*previous] {
+                can_finish[*previous] = true;
+                stack.push(*previous);
+            }
+        }
+    }
+    can_finish.iter().filter(|finish| !**finish).count()
+}
+
+/// Walk the player through the solution, going to each switch or panel in turn, and count the tiles
+/// they cover
+fn backtracking(
+    grid: &TileGrid<TuesdayTile>,
+    interactables: &Interactables,
+    initial: &PuzzleState,
+    solution: &[PuzzleAction],
+    start: &TilePoint,
+) -> usize {
+    let mut state = initial.clone();
+    let mut position = *start;
+    let mut walked = 0;
+    for action in solution {
+        let stops = match action {
+            PuzzleAction::Toggle(id) => vec![*id],
+            PuzzleAction::Connect { source, target } => vec![*source, *target],
+            PuzzleAction::Select { source, gate } => vec![*source, *gate],
+        };
+        let keys = held_keys(
+            interactables,
+            &reachable(grid, interactables, &state, start),
+        );
+        for id in stops {
+            let Some(point) = interactables.position(id) else {
+                continue;
+            };
+            let walked_to = walk(grid, interactables, &state, &keys, &position, &point);
+            if let Some((distance, end)) = walked_to {
+                walked += distance;
+                position = end;
+            }
+        }
+        state = apply(interactables, &state, *action);
+    }
+    walked
+}
+
+/// Shortest walk to somewhere an interactable can be used from, and where the player ends up
+fn walk(
+    grid: &TileGrid<TuesdayTile>,
+    interactables: &Interactables,
+    state: &PuzzleState,
+    keys: &[u8],
+    from: &TilePoint,
+    to: &TilePoint,
+) -> Option<(usize, TilePoint)> {
+    let mut seen = vec![vec![false; grid.height()]; grid.width()];
+    let mut queue = VecDeque::from([(from.x, from.y, None, 0)]);
+    while let Some((x, y, heading, distance)) = queue.pop_front() {
+        if x >= grid.width()
+            || y >= grid.height()
+            || seen[x][y]
+            || !is_passable(grid, interactables, state, keys, x, y, heading)
+        {
+            continue;
+        }
+        seen[x][y] = true;
+        if x.abs_diff(to.x) + y.abs_diff(to.y) <= 1 {
+            return Some((distance, TilePoint::new(x, y)));
+        }
+        queue.extend(steps(x, y).map(|(x, y, heading)| (x, y, Some(heading), distance + 1)));
+    }
+    None
+}
+
+/// The tiles next to this one, and which way the player heads to step onto them
+fn steps(x: usize, y: usize) -> [(usize, usize, Heading); 4] {
+    [
+        (x.wrapping_sub(1), y, Heading::Left),
+        (x + 1, y, Heading::Right),
+        (x, y.wrapping_sub(1), Heading::Up),
+        (x, y + 1, Heading::Down),
+    ]
+}
+
+/// Everything the player could do from where they can currently reach
+fn actions(
+    interactables: &Interactables,
+    state: &PuzzleState,
+    reached: &[Vec<bool>],
+) -> Vec<PuzzleAction> {
+    let switches = interactables
+        .switches
+        .iter()
+        .filter(|(_, p)| can_use(reached, p))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    let panels = interactables
+        .panels
+        .iter()
+        .filter(|(_, p)| can_use(reached, p))
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    let (gates, gate_kinds): (Vec<_>, Vec<_>) = interactables
+        .gates
+        .iter()
+        .filter(|(_, _, p)| can_use(reached, p))
+        .map(|(id, kind, _)| (*id, *kind))
+        .unzip();
+
+    let mut actions = switches
+        .iter()
+        .map(|id| PuzzleAction::Toggle(*id))
+        .collect::<Vec<_>>();
+    // doors can't be selected, so panels and gates are the only things the player can wire into
+    for source in switches.iter().chain(panels.iter()).chain(gates.iter()) {
+        for target in panels.iter() {
+            if source != target && state.links.get(target) != Some(source) {
+                actions.push(PuzzleAction::Connect {
+                    source: *source,
+                    target: *target,
+                });
+            }
+        }
+        for (gate, kind) in gates.iter().zip(gate_kinds.iter()) {
+            if source == gate {
+                continue;
+            }
+            if !state.gate_links.contains(&(*gate, *source)) {
+                actions.push(PuzzleAction::Connect {
+                    source: *source,
+                    target: *gate,
+                });
+            }
+            if *kind == GateKind::Mux && state.selectors.get(gate) != Some(source) {
+                actions.push(PuzzleAction::Select {
+                    source: *source,
+                    gate: *gate,
+                });
+            }
+        }
+    }
+    actions
+}
+
+fn apply(interactables: &Interactables, state: &PuzzleState, action: PuzzleAction) -> PuzzleState {
+    let mut next = state.clone();
+    match action {
+        PuzzleAction::Toggle(id) => {
+            if let Some(i) = interactables.switches.iter().position(|(s, _)| *s == id) {
+                next.switches[i] = !next.switches[i];
+            }
+        }
+        PuzzleAction::Connect { source, target } => {
+            if interactables.gates.iter().any(|(id, ..)| *id == target) {
+                next.gate_links.insert((target, source));
+            } else {
+                next.links.insert(target, source);
+            }
+        }
+        PuzzleAction::Select { source, gate } => {
+            next.selectors.insert(gate, source);
+        }
+    }
+    next
+}
+
+/// Follow the wiring back to a switch to see if a source is on. Panels pass along whatever is wired
+/// into them, and gates combine everything wired into them after a multiplexer's selector
+fn is_source_on(interactables: &Interactables, state: &PuzzleState, id: u8, depth: usize) -> bool {
+    if let Some(i) = interactables.switches.iter().position(|(s, _)| *s == id) {
+        return state.switches[i];
+    }
+    // panels and gates wired in a loop never turn on
+    if depth > interactables.panels.len() + interactables.gates.len() {
+        return false;
+    }
+    if let Some((_, kind, _)) = interactables.gates.iter().find(|(g, ..)| *g == id) {
+        let selector = match kind {
+            GateKind::Mux => Some(
+                state
+                    .selectors
+                    .get(&id)
+                    .is_some_and(|source| is_source_on(interactables, state, *source, depth + 1)),
+            ),
+            _ => None,
+        };
+        // the set is ordered by gate then source, so inputs come out in order of source id
+        let inputs = state
+            .gate_links
+            .range((id, 0)..=(id, u8::MAX))
+            .map(|(_, source)| is_source_on(interactables, state, *source, depth + 1));
+        let inputs = selector.into_iter().chain(inputs).collect::<Vec<_>>();
+        return kind.output(&inputs);
+    }
+    match state.links.get(&id) {
+        Some(source) => is_source_on(interactables, state, *source, depth + 1),
+        None => false,
+    }
+}
+
+fn is_door_powered(interactables: &Interactables, state: &PuzzleState, id: u8) -> bool {
+    let extra_on = interactables
+        .extra_links
+        .iter()
+        .filter(|(target, _)| *target == id)
+        .all(|(_, source)| is_source_on(interactables, state, *source, 0));
+    extra_on
+        && state
+            .links
+            .get(&id)
+            .is_some_and(|source| is_source_on(interactables, state, *source, 0))
+}
+
+/// Keycards the player has picked up by reaching them
+fn held_keys(interactables: &Interactables, reached: &[Vec<bool>]) -> Vec<u8> {
+    interactables
+        .keycards
+        .iter()
+        .filter(|(_, p)| reached[p.x][p.y])
+        .map(|(id, _)| *id)
+        .collect()
+}
+
+/// Floor tiles that can be walked on, ignoring doors
+fn is_walkable(grid: &TileGrid<TuesdayTile>, x: usize, y: usize) -> bool {
+    let floor = grid[x][y][BASE_LAYER].is_some_and(|t| !t.is_impassable());
+    let blocked = matches!(
+        grid[x][y][INTERACTABLES_LAYER],
+        Some(SwitchLeft(_)) | Some(SwitchRight(_))
+    );
+    floor && !blocked
+}
+
+/// Walkable tiles that aren't blocked by a closed door, when stepping onto them heading this way.
+/// Timed doors are treated as staying open, the solver doesn't know how far away anything is
+fn is_passable(
+    grid: &TileGrid<TuesdayTile>,
+    interactables: &Interactables,
+    state: &PuzzleState,
+    keys: &[u8],
+    x: usize,
+    y: usize,
+    heading: Option<Heading>,
+) -> bool {
+    let door_closed = match grid[x][y][INTERACTABLES_LAYER] {
+        Some(DoorFrame(id)) | Some(DoorFrameTimed(id)) | Some(DoorFrameAll(id)) => {
+            !is_door_powered(interactables, state, id)
+        }
+        // doors which start open close when they're powered
+        Some(DoorFrameOpen(id)) => is_door_powered(interactables, state, id),
+        Some(DoorFrameOneWay(id, way)) => {
+            !is_door_powered(interactables, state, id) || heading.is_some_and(|h| h != way)
+        }
+        Some(DoorFrameKeycard(id)) => !keys.contains(&id),
+        _ => false,
+    };
+    is_walkable(grid, x, y) && !door_closed
+}
+
+/// Flood fill from the start, treating closed doors as walls. Keycards are picked up as they're
+/// reached, which can open up more of the map
+fn reachable(
+    grid: &TileGrid<TuesdayTile>,
+    interactables: &Interactables,
+    state: &PuzzleState,
+    start: &TilePoint,
+) -> Vec<Vec<bool>> {
+    let mut keys = vec![];
+    loop {
+        let mut reached = vec![vec![false; grid.height()]; grid.width()];
+        let mut stack = vec![(start.x, start.y, None)];
+        while let Some((x, y, heading)) = stack.pop() {
+            if x >= grid.width()
+                || y >= grid.height()
+                || reached[x][y]
+                || !is_passable(grid, interactables, state, &keys, x, y, heading)
+            {
+                continue;
+            }
+            reached[x][y] = true;
+            stack.extend(steps(x, y).map(|(x, y, heading)| (x, y, Some(heading))));
+        }
+
+        let held = held_keys(interactables, &reached);
+        if held.len() == keys.len() {
+            return reached;
+        }
+        keys = held;
+    }
+}
+
+/// Interactables can be used while standing on or next to them
+fn can_use(reached: &[Vec<bool>], point: &TilePoint) -> bool {
+    let (x, y) = (point.x, point.y);
+    [
+        (x, y),
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ]
+    .iter()
+    .any(|(x, y)| {
+        reached
+            .get(*x)
+            .and_then(|col| col.get(*y))
+            .copied()
+            .unwrap_or(false)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::defs::ControlLink;
+    use crate::map::maze::MazeAlgorithm;
+    use crate::map::puzzle_gen::{PuzzleSpec, generate_puzzle};
+    use crate::map::special::starter_room::starter_room;
+
+    #[test]
+    fn starter_room_is_solvable() {
+        let puzzle = starter_room();
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &SolveGoal::Everything);
+
+        // wire the switch into the panel and flip it
+        let solution = report.solution.unwrap();
+        assert_eq!(solution.len(), 2);
+        assert!(solution.contains(&PuzzleAction::Connect {
+            source: 3,
+            target: 2
+        }));
+        assert!(solution.contains(&PuzzleAction::Toggle(3)));
+        assert!(report.unreachable.is_empty());
+        assert!(!report.truncated);
+    }
+
+    #[test]
+    fn starter_room_metrics() {
+        let report = solve(
+            &starter_room(),
+            &TilePoint::new(0, 5),
+            &SolveGoal::Everything,
+        );
+        let metrics = report.metrics.unwrap();
+        assert_eq!(metrics.toggles, 1);
+        assert_eq!(metrics.rewires, 1);
+        // over to the switch, then the panel by the door
+        assert_eq!(metrics.backtracking, 8);
+        assert_eq!(metrics.dead_ends, 0);
+        assert_eq!(metrics.difficulty(), 3.4);
+    }
+
+    #[test]
+    fn dead_ends() {
+        // the door starts open, but flipping the switch behind it locks the player out for good
+        let mut puzzle = starter_room();
+        puzzle.grid[3][4][INTERACTABLES_LAYER] = None;
+        puzzle.grid[4][0][INTERACTABLES_LAYER] = Some(SwitchRight(9));
+        puzzle.starting_links.push(ControlLink::new(9, 2));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &SolveGoal::Everything);
+
+        assert_eq!(report.solution, Some(vec![]));
+        assert_eq!(report.states, 2);
+        assert_eq!(report.metrics.unwrap().dead_ends, 1);
+    }
+
+    #[test]
+    fn door_starting_open() {
+        // an unpowered door that starts open can be walked straight through
+        let mut puzzle = starter_room();
+        puzzle.grid[5][2][INTERACTABLES_LAYER] = Some(DoorFrameOpen(1));
+        puzzle.starting_links.clear();
+        let goal = SolveGoal::Reach(TilePoint::new(5, 1));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        assert_eq!(report.solution, Some(vec![]));
+    }
+
+    #[test]
+    fn gates() {
+        // both switches have to be on to get through an and gate
+        let mut puzzle = starter_room();
+        puzzle.grid[6][2][INTERACTABLES_LAYER] = None;
+        puzzle.grid[7][4][INTERACTABLES_LAYER] = Some(SwitchLeft(4));
+        puzzle.grid[1][4][INTERACTABLES_LAYER] = Some(GateAnd(7));
+        puzzle.starting_links = vec![
+            ControlLink::new(3, 7),
+            ControlLink::new(4, 7),
+            ControlLink::new(7, 1),
+        ];
+        let goal = SolveGoal::Reach(TilePoint::new(5, 1));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        let solution = report.solution.unwrap();
+        assert_eq!(solution.len(), 2);
+        assert!(solution.contains(&PuzzleAction::Toggle(3)));
+        assert!(solution.contains(&PuzzleAction::Toggle(4)));
+
+        // a not gate holds the door open until its switch is flipped
+        puzzle.grid[1][4][INTERACTABLES_LAYER] = Some(GateNot(7));
+        puzzle.starting_links.remove(1);
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        assert_eq!(report.solution, Some(vec![]));
+    }
+
+    #[test]
+    fn wiring_into_gates() {
+        // the door opens once the switch is wired into the or gate and flipped
+        let mut puzzle = starter_room();
+        puzzle.grid[6][2][INTERACTABLES_LAYER] = None;
+        puzzle.grid[1][4][INTERACTABLES_LAYER] = Some(GateOr(7));
+        puzzle.starting_links = vec![ControlLink::new(7, 1)];
+        let goal = SolveGoal::Reach(TilePoint::new(5, 1));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        let solution = report.solution.unwrap();
+        assert_eq!(solution.len(), 2);
+        assert!(solution.contains(&PuzzleAction::Connect {
+            source: 3,
+            target: 7
+        }));
+        assert_eq!(report.metrics.unwrap().rewires, 1);
+
+        // a multiplexer passes along its first input, which is never on, until a switch that's
+        // on is wired into its selector
+        puzzle.grid[1][4][INTERACTABLES_LAYER] = Some(GateMux(7));
+        puzzle.grid[7][4][INTERACTABLES_LAYER] = Some(SwitchRight(4));
+        puzzle.starting_links = vec![
+            ControlLink::new(2, 7),
+            ControlLink::new(4, 7),
+            ControlLink::new(7, 1),
+        ];
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        assert_eq!(
+            report.solution,
+            Some(vec![PuzzleAction::Select { source: 4, gate: 7 }])
+        );
+
+        // wired in from the start it's already open
+        puzzle.starting_links.push(ControlLink::selector(4, 7));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &goal);
+        assert_eq!(report.solution, Some(vec![]));
+    }
+
+    #[test]
+    fn unwired_door_is_unsolvable() {
+        // without the panel wired to the door there is no way to open it
+        let mut puzzle = starter_room();
+        puzzle.starting_links.clear();
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &SolveGoal::Everything);
+        assert!(!report.is_solvable());
+
+        // the door itself can still be walked up to
+        assert!(report.unreachable.is_empty());
+        let behind_door = solve(
+            &puzzle,
+            &TilePoint::new(0, 5),
+            &SolveGoal::Reach(TilePoint::new(5, 1)),
+        );
+        assert!(!behind_door.is_solvable());
+    }
+
+    #[test]
+    fn unreachable_interactables() {
+        let mut puzzle = starter_room();
+        puzzle.starting_links.clear();
+        // a switch locked behind the door that nothing opens
+        puzzle.grid[4][0][INTERACTABLES_LAYER] = Some(SwitchLeft(9));
+        puzzle.grid[6][0][INTERACTABLES_LAYER] = Some(Keycard(10));
+        puzzle.grid[7][0][INTERACTABLES_LAYER] = Some(GateAnd(11));
+        let report = solve(&puzzle, &TilePoint::new(0, 5), &SolveGoal::Everything);
+        assert_eq!(
+            report.unreachable,
+            vec![
+                TilePoint::new(4, 0),
+                TilePoint::new(6, 0),
+                TilePoint::new(7, 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn generated_puzzles_are_solvable() {
+        let spec = PuzzleSpec {
+            doors: 3,
+            switches: 4,
+            panels: 2,
+            special_doors: 0,
+            difficulty: None,
+        };
+        for seed in 0..5 {
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let puzzle = generate_puzzle(48, 30, MazeAlgorithm::Wilson, &spec, &mut rng);
+            let start = puzzle.player_start().unwrap();
+            let report = solve(&puzzle, &start, &SolveGoal::Everything);
+            assert!(report.is_solvable(), "seed {seed}");
+            assert!(report.solution.unwrap().len() >= 3);
+        }
+    }
+}
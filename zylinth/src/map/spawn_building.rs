@@ -1,8 +1,22 @@
+use std::collections::HashMap;
+
+use super::biome::wing_depths;
 use super::decoration::decorate_empty;
-use super::special::starter_room::starter_room;
-use super::starter::mark_player_start_tile;
-use super::tuesday::TuesdayTile;
-use super::{NewMap, TileLayer, TileLayerRole};
+use super::functional_tiles::UtilityTile;
+use super::invariants::check_invariants;
+use super::maze::MazeAlgorithm;
+use super::maze_rooms::maze_building;
+use super::mission::{MissionSpec, generate_mission_puzzle};
+use super::puzzle::Puzzle;
+use super::puzzle_gen::{PuzzleSpec, generate_puzzle};
+use super::special::scatter_puzzle_rooms;
+use super::special::starter_room::{starter_room, starter_room_prefab};
+use super::starter::{mark_exit_tile, mark_player_start_tile};
+use super::tilemap::{RenderTileGrid, TileLayerConfig};
+use super::tileset::{Tilesets, world_tileset};
+use super::tuesday::{TuesdayTile, utility_grid_to_tuesday};
+use super::walking_squares::{WalkOptions, walking_squares};
+use super::{NewMap, TileLayerRole};
 use crate::defs::GameLayer;
 use crate::map::wall_wrap::wrap_walls;
 use crate::seed::RngSeed;
@@ -20,6 +34,61 @@ pub struct SpawnBuildingMap {
     pub wander_factor: f32,
     /// how much often this should split off in a new direction
     pub branch_factor: f32,
+    pub layout: BuildingLayout,
+    /// How many copies of the starter room to scatter through the building once it's laid out
+    pub puzzle_rooms: u8,
+    /// Tilesets to draw each wing of the building with, starting with the wing the player starts
+    /// in. Every door the player goes through leads one wing further in
+    pub biomes: Vec<String>,
+}
+
+/// How the rooms of the building are generated
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BuildingLayout {
+    /// The handcrafted starter room
+    StarterRoom,
+    /// Procedural rooms wandering out from the top left, shaped by the density, wander and branch factors
+    WalkingSquares(WalkOptions),
+    /// A room for every node of a maze, joined by corridors
+    Maze {
+        algorithm: MazeAlgorithm,
+        /// fraction of dead ends to turn into loops
+        braid: f32,
+    },
+    /// Maze rooms locked behind a chain of switch controlled doors
+    SwitchPuzzle {
+        algorithm: MazeAlgorithm,
+        spec: PuzzleSpec,
+    },
+    /// Rooms laid out from a mission graph, where each door's switch is found behind the door before it
+    Mission(MissionSpec),
+}
+
+impl SpawnBuildingMap {
+    fn walking_squares_building(
+        &self,
+        options: &WalkOptions,
+        biome: &HashMap<UtilityTile, TuesdayTile>,
+        rng: &mut ChaCha8Rng,
+    ) -> Puzzle<TuesdayTile> {
+        let utility = walking_squares(
+            self.width,
+            self.height,
+            self.density,
+            self.branch_factor,
+            self.wander_factor,
+            options,
+            rng,
+        );
+        let mut grid = utility_grid_to_tuesday(&utility, biome, rng);
+        grid.push_layer();
+
+        Puzzle {
+            grid,
+            starting_links: vec![],
+        }
+    }
 }
 
 impl Command for SpawnBuildingMap {
@@ -27,44 +96,104 @@ impl Command for SpawnBuildingMap {
         let seed = world.get_resource::<RngSeed>().unwrap();
         let mut rng = ChaCha8Rng::seed_from_u64(seed.0);
 
-        let mut puzzle = starter_room();
-        let mut grid = wrap_walls(puzzle.grid, &mut rng);
-        decorate_empty(&mut grid, &mut rng);
-        mark_player_start_tile(&mut grid, 1, &mut rng);
+        let mut puzzle = match self.layout {
+            BuildingLayout::StarterRoom => starter_room(),
+            BuildingLayout::WalkingSquares(options) => {
+                let biome = self
+                    .biomes
+                    .first()
+                    .and_then(|name| world_tileset(world, name))
+                    .map(|(_, tileset)| tileset.utility.clone())
+                    .unwrap_or_default();
+                self.walking_squares_building(&options, &biome, &mut rng)
+            }
+            BuildingLayout::Maze { algorithm, braid } => {
+                maze_building(self.width, self.height, algorithm, braid, &mut rng)
+            }
+            BuildingLayout::SwitchPuzzle { algorithm, spec } => {
+                generate_puzzle(self.width, self.height, algorithm, &spec, &mut rng)
+            }
+            BuildingLayout::Mission(spec) => {
+                generate_mission_puzzle(self.width, self.height, &spec, &mut rng)
+            }
+        };
+
+        let rooms = (0..self.puzzle_rooms)
+            .map(|_| starter_room_prefab())
+            .collect();
+        for err in scatter_puzzle_rooms(&mut puzzle, rooms, &mut rng) {
+            warn!("Unable to place puzzle room: {:?}", err);
+        }
+        spawn_puzzle(world, puzzle, &self.biomes, &mut rng);
+    }
+}
+
+/// Wrap walls around a puzzle, decorate it and spawn it as the current map, with each wing drawn
+/// in its biome's tileset
+pub fn spawn_puzzle(
+    world: &mut World,
+    puzzle: Puzzle<TuesdayTile>,
+    biomes: &[String],
+    rng: &mut ChaCha8Rng,
+) {
+    let mut grid = wrap_walls(puzzle.grid, rng);
+    decorate_empty(&mut grid, rng);
+    mark_player_start_tile(&mut grid, 1, rng);
+    mark_exit_tile(&mut grid);
+    if cfg!(debug_assertions) {
+        check_invariants(&grid);
+    }
 
-        // TODO: change this to a custom command instead of spawning TileLayer
-        world.spawn((
-            TileLayer {
-                grid: TuesdayTile::layer_to_tile_sprites(&grid, 0),
-                tileset_name: TuesdayTile::name(),
-                z: 0.0,
-                ..Default::default()
-            },
-            Transform::default(),
-        ));
-        world.spawn((
-            TileLayer {
-                grid: TuesdayTile::layer_to_tile_sprites(&grid, 1),
-                tileset_name: TuesdayTile::name(),
-                z: 1.0,
-                ..Default::default()
-            },
-            Transform::default(),
-        ));
-        world.spawn((
-            TileLayer {
-                grid: TuesdayTile::layer_to_tile_sprites(&grid, 2),
-                tileset_name: TuesdayTile::name(),
-                z: 5.0,
-                layer: GameLayer::Interactables,
-            },
-            Transform::default(),
-        ));
+    let wings = wing_depths(&grid);
+    let default_biome = [TuesdayTile::name().to_string()];
+    let biomes = if biomes.is_empty() {
+        &default_biome[..]
+    } else {
+        biomes
+    };
 
-        for door_control in puzzle.starting_links {
-            world.spawn(door_control);
+    // only the walls of the base layer cast shadows
+    let layers = [
+        (0.0, GameLayer::default(), false),
+        (1.0, GameLayer::default(), true),
+        (5.0, GameLayer::Interactables, false),
+    ];
+    // every wing past the last biome uses the last one
+    for (biome, name) in biomes.iter().enumerate() {
+        let Some(tileset) = world.resource::<Tilesets>().get(name) else {
+            error!("No tileset called {name} to draw the map with");
+            continue;
+        };
+        let mut wing = grid.clone();
+        for x in 0..wing.width() {
+            for y in 0..wing.height() {
+                if wings[x][y].min(biomes.len() - 1) != biome {
+                    wing[x][y].fill(None);
+                }
+            }
         }
 
-        world.send_event(NewMap);
+        for (grid_layer, (z, layer, occluders)) in layers.into_iter().enumerate() {
+            let empty = wing
+                .iter()
+                .flatten()
+                .all(|stack| stack[grid_layer].is_none());
+            if empty {
+                continue;
+            }
+            let config = TileLayerConfig {
+                tileset: tileset.clone(),
+                z,
+                layer,
+                occluders,
+            };
+            RenderTileGrid::new(wing.clone(), grid_layer, config).apply(world);
+        }
+    }
+
+    for door_control in puzzle.starting_links {
+        world.spawn(door_control);
     }
+
+    world.send_event(NewMap);
 }
@@ -1,2 +1,173 @@
 pub mod starter_room;
 pub mod test_room;
+
+use std::collections::{BTreeSet, HashMap};
+
+use bevy::prelude::*;
+use rand::Rng;
+use tilegen::{Prefab, PrefabError};
+
+use crate::defs::ControlLink;
+use crate::map::puzzle::Puzzle;
+use crate::map::tileset::NamedTile;
+use crate::map::tuesday::TuesdayTile;
+
+/// Stamp handcrafted puzzle rooms into random places within a generated building. Each room is
+/// given ids after the building's, so the doors, switches and panels of different rooms are never
+/// wired together. Rooms that can't be placed are skipped, and why each of them failed is returned
+pub fn scatter_puzzle_rooms(
+    puzzle: &mut Puzzle<TuesdayTile>,
+    rooms: Vec<(Prefab<TuesdayTile>, Vec<ControlLink>)>,
+    rng: &mut impl Rng,
+) -> Vec<PrefabError> {
+    let depth = rooms.iter().map(|(p, _)| p.grid.depth()).max().unwrap_or(0);
+    while puzzle.grid.depth() < depth {
+        puzzle.grid.push_layer();
+    }
+
+    let mut next_id = next_free_id(puzzle);
+    let mut failures = Vec::new();
+    for (prefab, room_links) in rooms {
+        let Some((prefab, room_links, after)) =
+            next_id.and_then(|first| relink_room(&prefab, &room_links, first))
+        else {
+            warn!("Ran out of ids for puzzle rooms");
+            break;
+        };
+        match prefab.stamp_rnd(&mut puzzle.grid, rng) {
+            Ok(rect) => {
+                debug!("Placed puzzle room at {:?}", rect);
+                puzzle.starting_links.extend(room_links);
+                next_id = after;
+            }
+            Err(err) => failures.push(err),
+        }
+    }
+    failures
+}
+
+/// The lowest id that nothing in the puzzle uses yet
+fn next_free_id(puzzle: &Puzzle<TuesdayTile>) -> Option<u8> {
+    let tiles = puzzle
+        .grid
+        .iter()
+        .flatten()
+        .flatten()
+        .flatten()
+        .filter(|tile| tile.with_link_id(0).is_some())
+        .map(|tile| tile.link_id());
+    let links = puzzle
+        .starting_links
+        .iter()
+        .flat_map(|link| [link.source, link.target]);
+    match tiles.chain(links).max() {
+        Some(id) => id.checked_add(1),
+        None => Some(1),
+    }
+}
+
+/// Give a room's ids new numbers starting from `first`, returning the room, its links and the id
+/// after the last one used. Exits and player starts are left behind, the building has its own
+fn relink_room(
+    prefab: &Prefab<TuesdayTile>,
+    links: &[ControlLink],
+    first: u8,
+) -> Option<(Prefab<TuesdayTile>, Vec<ControlLink>, Option<u8>)> {
+    let old_ids = prefab
+        .grid
+        .iter()
+        .flatten()
+        .flatten()
+        .flatten()
+        .filter(|tile| tile.with_link_id(0).is_some())
+        .map(|tile| tile.link_id())
+        .chain(links.iter().flat_map(|link| [link.source, link.target]))
+        .collect::<BTreeSet<_>>();
+    let mut ids = HashMap::new();
+    let mut next = Some(first);
+    for old in old_ids {
+        let new = next?;
+        ids.insert(old, new);
+        next = new.checked_add(1);
+    }
+
+    let mut grid = prefab.grid.clone();
+    for x in 0..grid.width() {
+        for y in 0..grid.height() {
+            for tile in grid[x][y].iter_mut() {
+                *tile = match *tile {
+                    Some(TuesdayTile::Exit) | Some(TuesdayTile::PlayerStart(_)) => None,
+                    Some(t) => Some(
+                        ids.get(&t.link_id())
+                            .and_then(|id| t.with_link_id(*id))
+                            .unwrap_or(t),
+                    ),
+                    None => None,
+                };
+            }
+        }
+    }
+    let links = links
+        .iter()
+        .map(|link| ControlLink {
+            select: link.select,
+            ..ControlLink::new(ids[&link.source], ids[&link.target])
+        })
+        .collect();
+    let prefab = Prefab {
+        grid,
+        anchors: prefab.anchors.clone(),
+    };
+    Some((prefab, links, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use tilegen::TileGrid;
+
+    use super::starter_room::starter_room_prefab;
+    use super::*;
+    use crate::map::tuesday::TuesdayTile::*;
+
+    #[test]
+    fn scatter_relinks_rooms() {
+        // a corridor down the left with two openings the rooms can be joined onto
+        let mut grid = TileGrid::empty(14, 13, 2);
+        for y in 0..grid.height() {
+            grid[0][y][0] = Some(Floor);
+        }
+        grid[1][3][0] = Some(Floor);
+        grid[1][10][0] = Some(Floor);
+        grid[0][0][1] = Some(SwitchLeft(4));
+        let mut puzzle = Puzzle {
+            grid,
+            starting_links: vec![],
+        };
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let rooms = vec![
+            starter_room_prefab(),
+            starter_room_prefab(),
+            starter_room_prefab(),
+        ];
+        let failures = scatter_puzzle_rooms(&mut puzzle, rooms, &mut rng);
+        assert_eq!(failures, vec![PrefabError::NoSpace]);
+
+        // each room gets its own ids after the building's switch
+        let mut links = puzzle
+            .starting_links
+            .iter()
+            .map(|l| (l.source, l.target))
+            .collect::<Vec<_>>();
+        links.sort();
+        assert_eq!(links, vec![(6, 5), (9, 8)]);
+        let tiles = puzzle.grid.iter().flatten().flatten().flatten();
+        let doors = tiles.clone().filter(|t| t.door_id().is_some()).count();
+        assert_eq!(doors, 2);
+        assert!(tiles.clone().any(|t| *t == DoorFrame(8)));
+        assert!(tiles.clone().any(|t| *t == SwitchLeft(10)));
+        assert!(!tiles.clone().any(|t| *t == Exit));
+    }
+}
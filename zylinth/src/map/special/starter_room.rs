@@ -1,4 +1,4 @@
-use tilegen::TileGrid;
+use tilegen::{Prefab, PrefabAnchor, TileDir, TileGrid, TilePoint};
 
 use crate::defs::ControlLink;
 use crate::map::puzzle::Puzzle;
@@ -14,6 +14,7 @@ pub fn starter_room() -> Puzzle<TuesdayTile> {
     grid[3][0][0] = Some(Floor);
     grid[4][0][0] = Some(Floor);
     grid[5][0][0] = Some(Floor);
+    grid[5][0][1] = Some(Exit);
     grid[6][0][0] = Some(Floor);
     grid[7][0][0] = Some(Floor);
     grid[7][0][1] = Some(Resoursce1);
@@ -90,3 +91,22 @@ pub fn starter_room() -> Puzzle<TuesdayTile> {
         starting_links: vec![ControlLink::new(2, 1)],
     }
 }
+
+/// The starter room as a prefab that can be dropped into a generated building.
+/// It is entered through the corridor on its left side
+pub fn starter_room_prefab() -> (Prefab<TuesdayTile>, Vec<ControlLink>) {
+    let puzzle = starter_room();
+    let prefab = Prefab::new(puzzle.grid)
+        // leave space for walls to be wrapped around it
+        .with_anchor(PrefabAnchor::Clearance {
+            margin: 1,
+            layer: 0,
+        })
+        .with_anchor(PrefabAnchor::Socket {
+            point: TilePoint::new(0, 3),
+            dir: TileDir::Left,
+            layer: 0,
+            condition: |t| *t == Some(Floor),
+        });
+    (prefab, puzzle.starting_links)
+}
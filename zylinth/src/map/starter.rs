@@ -1,3 +1,6 @@
+use std::collections::VecDeque;
+
+use super::IsImpassable;
 use super::tuesday::{TuesdayTile, TuesdayTile::*};
 use bevy::prelude::*;
 use rand::Rng;
@@ -8,6 +11,16 @@ pub fn mark_player_start_tile(
     start_position_count: u8,
     rng: &mut impl Rng,
 ) {
+    // generators which already picked a start know better where it should go
+    let has_start = grid
+        .iter()
+        .flatten()
+        .flatten()
+        .any(|t| matches!(t, Some(PlayerStart(_))));
+    if has_start {
+        return;
+    }
+
     let z = grid.depth() - 1;
     grid.apply_layer_replacements(
         z,
@@ -26,3 +39,53 @@ pub fn mark_player_start_tile(
         rng,
     );
 }
+
+/// Put the exit on the free floor tile furthest from the player start, unless the generator
+/// already placed one
+pub fn mark_exit_tile(grid: &mut TileGrid<TuesdayTile>) {
+    let z = grid.depth() - 1;
+    let mut start = None;
+    for x in 0..grid.width() {
+        for y in 0..grid.height() {
+            match grid[x][y][z] {
+                Some(Exit) => return,
+                Some(PlayerStart(_)) => start = Some((x, y)),
+                _ => {}
+            }
+        }
+    }
+    let Some(start) = start else {
+        warn!("No player start to place the exit away from");
+        return;
+    };
+
+    // walk out from the start, going through doors but around switches
+    let mut seen = vec![vec![false; grid.height()]; grid.width()];
+    let mut queue = VecDeque::from([start]);
+    let mut furthest = None;
+    while let Some((x, y)) = queue.pop_front() {
+        if x >= grid.width() || y >= grid.height() || seen[x][y] {
+            continue;
+        }
+        seen[x][y] = true;
+        let walkable = grid[x][y][z - 1].is_some_and(|t| !t.is_impassable())
+            && !matches!(grid[x][y][z], Some(SwitchLeft(_)) | Some(SwitchRight(_)));
+        if !walkable {
+            continue;
+        }
+        if grid[x][y][z - 1] == Some(Floor) && grid[x][y][z].is_none() {
+            furthest = Some((x, y));
+        }
+        queue.extend([
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ]);
+    }
+
+    match furthest {
+        Some((x, y)) => grid[x][y][z] = Some(Exit),
+        None => warn!("No free floor for the exit"),
+    }
+}
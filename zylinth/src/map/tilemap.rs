@@ -1,14 +1,22 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 
 use avian2d::prelude::*;
 use bevy::prelude::*;
-use std::cmp;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy_lit::prelude::LightOccluder2d;
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use tilegen::TileGrid;
 
 use crate::defs::GameLayer;
+use crate::seed::RngSeed;
 
 use super::TileRole;
-use super::plugin::{TileLayer, TileSprite};
-use super::tileset::{Tileset, TilesetId};
+use super::colliders::{ColliderRect, merge_rects};
+use super::plugin::TileSprite;
+use super::tileset::{NamedTile, TileLight, Tileset};
 
 #[allow(unused)]
 #[derive(Component, Debug, Default)]
@@ -17,194 +25,814 @@ pub struct Tile {
     pub grid_y: u32,
     pub width: u8,
     pub height: u8,
-    pub tileset_name: &'static str,
+    pub tileset: Handle<Tileset>,
     pub role: Option<TileRole>,
+    /// Light of the tile it was spawned as, which stays while its sprite changes to show it's
+    /// selected
+    pub light: Option<TileLight>,
 }
 
+/// Width and height in tiles of the chunks that static tiles are batched into
+const CHUNK_SIZE: usize = 16;
+
+/// A layer of tiles drawn with one tileset. The tiles, chunks and colliders are its children
 #[allow(unused)]
 #[derive(Component, Debug)]
 pub struct Tilemap {
     pub width: u32,
     pub height: u32,
+    pub config: TileLayerConfig,
+    sprites: Vec<Vec<Option<TileSprite>>>,
+    /// Tiles which have their own entity
+    tiles: Vec<Vec<Option<Entity>>>,
+    chunks: Vec<Vec<Option<Entity>>>,
+    /// Every collider along with the tiles it covers
+    colliders: Vec<(ColliderRect, Entity)>,
+    material: Handle<ColorMaterial>,
+}
+
+impl Tilemap {
+    /// Grid coordinates of the tile under a point relative to the tilemap, if there is a tile there
+    pub fn tile_at(&self, position: Vec2, tileset: &Tileset) -> Option<(usize, usize)> {
+        let x = (position.x / tileset.tile_width as f32).round();
+        let y = ((self.width as f32 - 1.0 - position.y) / tileset.tile_height as f32).round();
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let (x, y) = (x as usize, y as usize);
+        self.sprites.get(x)?.get(y)?.as_ref().map(|_| (x, y))
+    }
+}
+
+/// A single mesh drawing every static tile in one chunk of a tilemap
+#[allow(unused)]
+#[derive(Component, Debug)]
+pub struct TileChunk {
+    pub x: usize,
+    pub y: usize,
+}
+
+/// How a layer of tiles is drawn
+#[derive(Debug, Clone, Default)]
+pub struct TileLayerConfig {
+    pub tileset: Handle<Tileset>,
+    pub z: f32,
+    /// Physics layer of the colliders
+    pub layer: GameLayer,
+    /// Whether the colliders also cast shadows
+    pub occluders: bool,
 }
 
 #[derive(Event)]
 pub struct RenderedTileLayer;
 
-/// Whenever a new TileLayer is spawned this system will detect it and
-/// render it into sprites
-pub fn render_tilemap(
-    trigger: Trigger<OnAdd, TileLayer>,
-    query: Query<(&TileLayer, &Transform)>,
-    tileset_ids: Query<(&Name, &TilesetId)>,
-    tileset_assets: Res<Assets<Tileset>>,
-    mut commands: Commands,
-    mut ev_rendered: EventWriter<RenderedTileLayer>,
-) {
-    let (layer, transform) = query.get(trigger.entity()).unwrap();
-    let tileset_id = tileset_ids
-        .iter()
-        .find(|(name, _)| **name == Name::new(layer.tileset_name))
-        .map(|(_, id)| id)
-        .unwrap();
-    let tileset = tileset_assets.get(tileset_id.id).unwrap();
-
-    let width = layer.grid.len() as u32;
-    let height = layer.grid[0].len() as u32;
-    let tilemap_entity = commands
-        .spawn((Tilemap { width, height }, *transform, Visibility::Visible))
-        .add_child(trigger.entity())
-        .id();
-
-    let mut tile_entities: Vec<Entity> = Vec::new();
-    let collisions = spawn_collisions(
-        width,
-        height,
-        tileset,
-        &layer.grid,
-        layer.layer,
-        &mut commands,
-    );
-
-    for x in 0..width {
-        for y in 0..height {
-            if let Some(t) = &layer.grid[x as usize][y as usize] {
-                let offset_x = x as f32 * tileset.tile_width as f32;
-                let offset_y = y as f32 * tileset.tile_height as f32;
-                // sprite maps are rendered with 0,0 in the bottom left so flip the Y coord
-                let flipped_y = width as f32 - offset_y - 1.0;
-
-                if let Some(role) = &t.role {
-                    debug!("Inserting a {:?} at {x},{y}", role);
-                }
+/// Make a tile of a tilemap start or stop blocking things, eg a wall being knocked down or a
+/// bridge appearing. Only the colliders around the tile are rebuilt
+#[allow(unused)]
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SetTileCollider {
+    pub tilemap: Entity,
+    pub grid_x: u32,
+    pub grid_y: u32,
+    pub collider: bool,
+}
 
-                let mut tile_entity = commands.spawn((
-                    Tile {
-                        grid_x: x,
-                        grid_y: y,
-                        width: tileset.tile_width,
-                        height: tileset.tile_height,
-                        tileset_name: layer.tileset_name,
-                        role: t.role.clone(),
-                    },
-                    Sprite {
-                        image: tileset.image.clone(),
-                        texture_atlas: Some(TextureAtlas {
-                            layout: tileset.layout.clone(),
-                            index: t.index,
-                        }),
-                        ..default()
-                    },
-                    Transform::from_xyz(offset_x, flipped_y, layer.z),
-                    // TODO: make this a nice debug plugin or something
-                    // TextFont {
-                    //     font_size: 8.0,
-                    //     ..Default::default()
-                    // },
-                    // Text2d::new(format!("({},{})", x, y)),
-                ));
-
-                // attach collider to top-left tile
-                if let Some(collider) = &collisions[x as usize][y as usize] {
-                    tile_entity.add_child(*collider);
+/// Custom command to draw one layer of a tile grid as a tilemap
+pub struct RenderTileGrid<T: Clone + PartialEq + Eq> {
+    pub grid: TileGrid<T>,
+    /// Which layer of the grid is drawn
+    pub grid_layer: usize,
+    pub config: TileLayerConfig,
+    /// Redraw this tilemap rather than spawning a new one
+    pub target: Option<Entity>,
+}
+
+impl<T: Clone + PartialEq + Eq> RenderTileGrid<T> {
+    pub fn new(grid: TileGrid<T>, grid_layer: usize, config: TileLayerConfig) -> Self {
+        Self {
+            grid,
+            grid_layer,
+            config,
+            target: None,
+        }
+    }
+
+    /// Replace everything in an existing tilemap instead of spawning a new one
+    #[allow(unused)]
+    pub fn into_tilemap(mut self, tilemap: Entity) -> Self {
+        self.target = Some(tilemap);
+        self
+    }
+}
+
+impl<T> Command for RenderTileGrid<T>
+where
+    T: NamedTile + Copy + Eq + Send + Sync + 'static,
+{
+    fn apply(self, world: &mut World) {
+        let Some(tileset) = loaded_tileset(world, &self.config.tileset) else {
+            return;
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(world.resource::<RngSeed>().0);
+        let sprites = tileset.layer_to_tile_sprites(&self.grid, self.grid_layer, &mut rng);
+
+        let entity = match self.target {
+            Some(entity) => {
+                let Some(tilemap) = world.entity_mut(entity).take::<Tilemap>() else {
+                    error!("Cannot redraw {entity}, it is not a tilemap");
+                    return;
+                };
+                clear_tilemap(world, tilemap);
+                entity
+            }
+            None => world
+                .spawn((Transform::default(), Visibility::Visible))
+                .id(),
+        };
+
+        let width = sprites.len();
+        let height = sprites.first().map_or(0, |col| col.len());
+        let material = world
+            .resource_mut::<Assets<ColorMaterial>>()
+            .add(ColorMaterial::from(tileset.image.clone()));
+        let draw = TileDraw {
+            tilemap: entity,
+            width,
+            tileset: &tileset,
+            config: &self.config,
+            material: &material,
+        };
+        let mut tiles = vec![vec![None; height]; width];
+        for x in 0..width {
+            for y in 0..height {
+                if let Some(sprite) = sprites[x][y].as_ref().filter(|s| draw.needs_entity(s)) {
+                    tiles[x][y] = Some(draw.spawn_tile(world, x, y, sprite));
                 }
+            }
+        }
+        let mut chunks = vec![vec![None; height.div_ceil(CHUNK_SIZE)]; width.div_ceil(CHUNK_SIZE)];
+        for (cx, col) in chunks.iter_mut().enumerate() {
+            for (cy, chunk) in col.iter_mut().enumerate() {
+                *chunk = draw.spawn_chunk(world, &sprites, cx, cy);
+            }
+        }
+        let colliders = ColliderDraw {
+            tilemap: entity,
+            width,
+            tileset: &tileset,
+            config: &self.config,
+        }
+        .spawn_all(world, &sprites);
 
-                tile_entities.push(tile_entity.id());
+        world.entity_mut(entity).insert(Tilemap {
+            width: width as u32,
+            height: height as u32,
+            config: self.config,
+            sprites,
+            tiles,
+            chunks,
+            colliders,
+            material,
+        });
+        world.send_event(RenderedTileLayer);
+    }
+}
+
+/// Custom command to swap some of the tiles of a tilemap in place. `None` removes the tile
+#[allow(unused)]
+pub struct PatchTilemap<T> {
+    pub tilemap: Entity,
+    pub tiles: Vec<(usize, usize, Option<T>)>,
+}
+
+impl<T> Command for PatchTilemap<T>
+where
+    T: NamedTile + Copy + Send + Sync + 'static,
+{
+    fn apply(self, world: &mut World) {
+        let Some(mut tilemap) = world.entity_mut(self.tilemap).take::<Tilemap>() else {
+            error!("Cannot patch {}, it is not a tilemap", self.tilemap);
+            return;
+        };
+        let Some(tileset) = loaded_tileset(world, &tilemap.config.tileset) else {
+            world.entity_mut(self.tilemap).insert(tilemap);
+            return;
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(world.resource::<RngSeed>().0);
+
+        let width = tilemap.width as usize;
+        let height = tilemap.height as usize;
+        let config = tilemap.config.clone();
+        let material = tilemap.material.clone();
+        let draw = TileDraw {
+            tilemap: self.tilemap,
+            width,
+            tileset: &tileset,
+            config: &config,
+            material: &material,
+        };
+        let mut collider_changes = Vec::new();
+        let mut dirty_chunks = HashSet::new();
+        for (x, y, tile) in self.tiles {
+            if x >= width || y >= height {
+                warn!("Cannot patch {x},{y}, it is outside of the tilemap");
+                continue;
+            }
+            let sprite = tile.and_then(|t| tileset.sprite(&t, &mut rng));
+            if has_collider(&tilemap.sprites[x][y]) != has_collider(&sprite) {
+                collider_changes.push((x, y));
+            }
+
+            let in_chunk =
+                |s: &Option<TileSprite>| s.as_ref().is_some_and(|s| !draw.needs_entity(s));
+            if in_chunk(&tilemap.sprites[x][y]) || in_chunk(&sprite) {
+                dirty_chunks.insert((x / CHUNK_SIZE, y / CHUNK_SIZE));
             }
+
+            if let Some(old) = tilemap.tiles[x][y].take() {
+                world.entity_mut(old).despawn_recursive();
+            }
+            if let Some(sprite) = sprite.as_ref().filter(|s| draw.needs_entity(s)) {
+                tilemap.tiles[x][y] = Some(draw.spawn_tile(world, x, y, sprite));
+            }
+            tilemap.sprites[x][y] = sprite;
         }
+
+        for (cx, cy) in dirty_chunks {
+            if let Some(old) = tilemap.chunks[cx][cy].take() {
+                world.entity_mut(old).despawn_recursive();
+            }
+            tilemap.chunks[cx][cy] = draw.spawn_chunk(world, &tilemap.sprites, cx, cy);
+        }
+
+        let draw = ColliderDraw {
+            tilemap: self.tilemap,
+            width,
+            tileset: &tileset,
+            config: &config,
+        };
+        draw.update(world, &mut tilemap, &collider_changes);
+        world.entity_mut(self.tilemap).insert(tilemap);
     }
+}
 
-    // set tiles as child of parent so that transforms cascade
-    for tile in tile_entities {
-        commands.entity(tilemap_entity).add_child(tile);
+/// Custom command to change which tiles of a tilemap block things without redrawing them
+pub struct SetTileColliders {
+    pub tilemap: Entity,
+    pub tiles: Vec<(usize, usize, bool)>,
+}
+
+impl Command for SetTileColliders {
+    fn apply(self, world: &mut World) {
+        let Some(mut tilemap) = world.entity_mut(self.tilemap).take::<Tilemap>() else {
+            error!(
+                "Cannot change colliders of {}, it is not a tilemap",
+                self.tilemap
+            );
+            return;
+        };
+        let Some(tileset) = loaded_tileset(world, &tilemap.config.tileset) else {
+            world.entity_mut(self.tilemap).insert(tilemap);
+            return;
+        };
+
+        let mut changed = Vec::new();
+        for (x, y, collider) in self.tiles {
+            let Some(sprite) = tilemap.sprites.get_mut(x).and_then(|col| col.get_mut(y)) else {
+                warn!("Cannot change the collider at {x},{y}, it is outside of the tilemap");
+                continue;
+            };
+            let Some(sprite) = sprite.as_mut() else {
+                warn!("Cannot change the collider at {x},{y}, there is no tile there");
+                continue;
+            };
+            if sprite.collider != collider {
+                sprite.collider = collider;
+                changed.push((x, y));
+            }
+        }
+
+        let config = tilemap.config.clone();
+        let draw = ColliderDraw {
+            tilemap: self.tilemap,
+            width: tilemap.width as usize,
+            tileset: &tileset,
+            config: &config,
+        };
+        draw.update(world, &mut tilemap, &changed);
+        world.entity_mut(self.tilemap).insert(tilemap);
     }
+}
 
-    ev_rendered.send(RenderedTileLayer);
+/// Apply every `SetTileCollider` sent this frame, batched per tilemap
+pub fn update_tile_colliders(
+    mut ev_collider: EventReader<SetTileCollider>,
+    mut commands: Commands,
+) {
+    let mut changes: HashMap<Entity, Vec<(usize, usize, bool)>> = HashMap::new();
+    for event in ev_collider.read() {
+        changes.entry(event.tilemap).or_default().push((
+            event.grid_x as usize,
+            event.grid_y as usize,
+            event.collider,
+        ));
+    }
+    for (tilemap, tiles) in changes {
+        commands.queue(SetTileColliders { tilemap, tiles });
+    }
 }
 
-/// It becomes way too imperformant to create a collider per tile
-/// Instead this method takes a grid of tiles and finds all neighboring impassible tiles
-/// Then resolves those into a set of colliders mapped to the tile it should be inserted on
-fn spawn_collisions(
-    width: u32,
-    height: u32,
-    tileset: &Tileset,
-    grid: &Vec<Vec<Option<TileSprite>>>,
-    layer: GameLayer,
-    commands: &mut Commands,
-) -> Vec<Vec<Option<Entity>>> {
-    let mut grid = grid.clone();
+/// The tileset if it has loaded, otherwise reports why the map can't be drawn
+fn loaded_tileset(world: &World, handle: &Handle<Tileset>) -> Option<Tileset> {
+    let tileset = world.resource::<Assets<Tileset>>().get(handle).cloned();
+    if tileset.is_none() {
+        match handle.path() {
+            Some(path) => error!("Cannot draw tiles, tileset {path} has not loaded"),
+            None => error!(
+                "Cannot draw tiles, tileset {:?} does not exist",
+                handle.id()
+            ),
+        }
+    }
+    tileset
+}
 
-    // group all horizontal neighbors
-    let mut contiguous_impassables: Vec<(u32, u32, u32, u32)> = Vec::new();
-    for y in 0..height {
-        let mut start_x: Option<u32> = None;
-        for x in 0..width {
-            let tile = &grid[x as usize][y as usize];
-            let has_collider = tile.clone().map_or(false, |t| t.collider);
-
-            if start_x.is_none() && has_collider {
-                // if there is no start x and the current tile is impassible
-                start_x = Some(x);
-
-                // if there is a start x AND this tile is not impassable or not defined or it's the last tile
-            } else if start_x.is_some() && (!has_collider || x == (width - 1)) {
-                if let Some(start) = start_x {
-                    // only save regions more than 1
-                    if x - start > 2 {
-                        // remove these tiles from the grid so as not to duplicate them with the vertical pass
-                        for nx in start..=x {
-                            grid[nx as usize][y as usize] = None;
-                        }
-                        contiguous_impassables.push((start, y, x, y));
-                    }
-                    start_x = None;
-                }
+/// Despawn everything that was drawn into a tilemap
+fn clear_tilemap(world: &mut World, tilemap: Tilemap) {
+    let tiles = tilemap.tiles.into_iter().flatten().flatten();
+    let chunks = tilemap.chunks.into_iter().flatten().flatten();
+    let colliders = tilemap.colliders.into_iter().map(|(_, entity)| entity);
+    for entity in tiles.chain(chunks).chain(colliders) {
+        world.entity_mut(entity).despawn_recursive();
+    }
+}
+
+/// Everything needed to draw tiles into a tilemap
+struct TileDraw<'a> {
+    tilemap: Entity,
+    width: usize,
+    tileset: &'a Tileset,
+    config: &'a TileLayerConfig,
+    material: &'a Handle<ColorMaterial>,
+}
+
+impl TileDraw<'_> {
+    /// Tiles that something needs to find or change get their own entity, the rest are batched
+    /// into chunks
+    fn needs_entity(&self, sprite: &TileSprite) -> bool {
+        sprite.role.is_some() || sprite.light.is_some()
+    }
+
+    /// Draw every static tile of a chunk as one mesh, if the chunk has any
+    fn spawn_chunk(
+        &self,
+        world: &mut World,
+        sprites: &[Vec<Option<TileSprite>>],
+        cx: usize,
+        cy: usize,
+    ) -> Option<Entity> {
+        let half_width = self.tileset.tile_width as f32 / 2.0;
+        let half_height = self.tileset.tile_height as f32 / 2.0;
+
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut indices: Vec<u32> = Vec::new();
+        let columns = sprites.iter().enumerate().skip(cx * CHUNK_SIZE);
+        for (x, col) in columns.take(CHUNK_SIZE) {
+            for (y, sprite) in col
+                .iter()
+                .enumerate()
+                .skip(cy * CHUNK_SIZE)
+                .take(CHUNK_SIZE)
+            {
+                let Some(sprite) = sprite.as_ref().filter(|s| !self.needs_entity(s)) else {
+                    continue;
+                };
+                let center = tile_position(x, y, self.width, self.tileset);
+                let (left, right) = (center.x - half_width, center.x + half_width);
+                let (top, bottom) = (center.y + half_height, center.y - half_height);
+                let [u0, v0, u1, v1] = self.tileset.uv(sprite.index);
+
+                let i = positions.len() as u32;
+                positions.extend([
+                    [left, top, 0.0],
+                    [right, top, 0.0],
+                    [right, bottom, 0.0],
+                    [left, bottom, 0.0],
+                ]);
+                uvs.extend([[u0, v0], [u1, v0], [u1, v1], [u0, v1]]);
+                indices.extend([i, i + 3, i + 2, i, i + 2, i + 1]);
             }
         }
+        if positions.is_empty() {
+            return None;
+        }
+
+        let normals = vec![[0.0, 0.0, 1.0]; positions.len()];
+        let mesh = Mesh::new(
+            PrimitiveTopology::TriangleList,
+            RenderAssetUsages::RENDER_WORLD,
+        )
+        .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+        .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+        .with_inserted_indices(Indices::U32(indices));
+        let mesh = world.resource_mut::<Assets<Mesh>>().add(mesh);
+
+        let chunk = world
+            .spawn((
+                TileChunk { x: cx, y: cy },
+                Mesh2d(mesh),
+                MeshMaterial2d(self.material.clone()),
+                Transform::from_xyz(0.0, 0.0, self.config.z),
+            ))
+            .id();
+        world.entity_mut(self.tilemap).add_child(chunk);
+        Some(chunk)
     }
 
-    // group all vertical neighbors excluding those who have been grouped by the horizontal grouping
-    for x in 0..width {
-        let mut start_y: Option<u32> = None;
-        for y in 0..height {
-            let tile = &grid[x as usize][y as usize];
-            let has_collider = tile.clone().map_or(false, |t| t.collider);
+    fn spawn_tile(&self, world: &mut World, x: usize, y: usize, sprite: &TileSprite) -> Entity {
+        let Self {
+            tilemap,
+            width,
+            tileset,
+            config,
+            ..
+        } = *self;
+        if let Some(role) = &sprite.role {
+            debug!("Inserting a {:?} at {x},{y}", role);
+        }
 
-            if start_y.is_none() && has_collider {
-                // if there is no start x and the current tile is impassible
-                start_y = Some(y);
+        let position = tile_position(x, y, width, tileset);
+        let tile = world
+            .spawn((
+                Tile {
+                    grid_x: x as u32,
+                    grid_y: y as u32,
+                    width: tileset.tile_width,
+                    height: tileset.tile_height,
+                    tileset: config.tileset.clone(),
+                    role: sprite.role.clone(),
+                    light: sprite.light,
+                },
+                Sprite {
+                    image: tileset.image.clone(),
+                    texture_atlas: Some(TextureAtlas {
+                        layout: tileset.layout.clone(),
+                        index: sprite.index,
+                    }),
+                    ..default()
+                },
+                Transform::from_translation(position.extend(config.z)),
+                // TODO: make this a nice debug plugin or something
+                // TextFont {
+                //     font_size: 8.0,
+                //     ..Default::default()
+                // },
+                // Text2d::new(format!("({},{})", x, y)),
+            ))
+            .id();
+        // set tiles as child of parent so that transforms cascade
+        world.entity_mut(tilemap).add_child(tile);
+        tile
+    }
+}
 
-                // if there is a start x AND this tile is not impassable or not defined or it's the last tile
-            } else if start_y.is_some() && (!has_collider || y == (height - 1)) {
-                if let Some(start) = start_y {
-                    contiguous_impassables.push((x, start, x, y));
-                    start_y = None;
-                }
+/// Where a tile sits in its tilemap
+fn tile_position(x: usize, y: usize, width: usize, tileset: &Tileset) -> Vec2 {
+    let offset_x = x as f32 * tileset.tile_width as f32;
+    let offset_y = y as f32 * tileset.tile_height as f32;
+    // sprite maps are rendered with 0,0 in the bottom left so flip the Y coord
+    let flipped_y = width as f32 - offset_y - 1.0;
+    Vec2::new(offset_x, flipped_y)
+}
+
+/// Everything needed to spawn the colliders of a tilemap
+struct ColliderDraw<'a> {
+    tilemap: Entity,
+    width: usize,
+    tileset: &'a Tileset,
+    config: &'a TileLayerConfig,
+}
+
+impl ColliderDraw<'_> {
+    /// It becomes way too imperformant to create a collider per tile
+    /// Instead this method merges all the impassable tiles into as few rectangles as it can
+    /// Then spawns a collider for each one which is added to the tilemap, along with a light
+    /// occluder of the same size if the layer casts shadows
+    fn spawn_all(
+        &self,
+        world: &mut World,
+        grid: &[Vec<Option<TileSprite>>],
+    ) -> Vec<(ColliderRect, Entity)> {
+        let solid: Vec<Vec<bool>> = grid
+            .iter()
+            .map(|col| col.iter().map(has_collider).collect())
+            .collect();
+        merge_rects(&solid)
+            .into_iter()
+            .map(|rect| (rect, self.spawn(world, rect)))
+            .collect()
+    }
+
+    /// Rebuild only the colliders covering tiles which changed. The rest of each of those
+    /// rectangles is merged again along with the changed tiles, everything else is left alone
+    fn update(&self, world: &mut World, tilemap: &mut Tilemap, changed: &[(usize, usize)]) {
+        if changed.is_empty() {
+            return;
+        }
+        let width = tilemap.sprites.len();
+        let height = tilemap.sprites.first().map_or(0, |col| col.len());
+        let mut loose = vec![vec![false; height]; width];
+        for &(x, y) in changed {
+            loose[x][y] = true;
+        }
+
+        let (stale, kept): (Vec<_>, Vec<_>) = tilemap
+            .colliders
+            .drain(..)
+            .partition(|(rect, _)| changed.iter().any(|&(x, y)| rect.contains(x, y)));
+        tilemap.colliders = kept;
+        for (rect, entity) in stale {
+            world.entity_mut(entity).despawn_recursive();
+            for col in loose.iter_mut().skip(rect.x).take(rect.width) {
+                col[rect.y..rect.y + rect.height].fill(true);
             }
         }
+
+        let solid: Vec<Vec<bool>> = tilemap
+            .sprites
+            .iter()
+            .zip(&loose)
+            .map(|(col, loose)| {
+                col.iter()
+                    .zip(loose)
+                    .map(|(sprite, loose)| *loose && has_collider(sprite))
+                    .collect()
+            })
+            .collect();
+        for rect in merge_rects(&solid) {
+            tilemap.colliders.push((rect, self.spawn(world, rect)));
+        }
     }
 
-    // turn the list of collision neighbors into collider regions
-    let mut colliders = vec![vec![None; height as usize]; width as usize];
-    for (x0, y0, x1, y1) in contiguous_impassables {
+    fn spawn(&self, world: &mut World, rect: ColliderRect) -> Entity {
+        let Self {
+            tilemap,
+            width,
+            tileset,
+            config,
+        } = *self;
         // x/y here are grid coordinates so they need to be scaled into
         // since they will be added to the parent the transform can be relative to the parent tilemap's position
-        let width = cmp::max(x1 - x0, 1) * tileset.tile_width as u32;
-        let height = cmp::max(y1 - y0, 1) * tileset.tile_height as u32;
-
-        let collider = commands.spawn((
-            RigidBody::Static,
-            Collider::rectangle(width as f32, height as f32),
-            CollisionLayers::new(layer, [GameLayer::Interactables, GameLayer::Player]),
-            Transform::from_xyz(
-                (width as f32 / 2.0) - (tileset.tile_width as f32 / 2.0),
-                (height as f32 / -2.0) + (tileset.tile_height as f32 / 2.0),
-                0.1,
-            ),
-        ));
-        colliders[x0 as usize][y0 as usize] = Some(collider.id());
+        let collider_width = (rect.width * tileset.tile_width as usize) as f32;
+        let collider_height = (rect.height * tileset.tile_height as usize) as f32;
+        let corner = tile_position(rect.x, rect.y, width, tileset);
+
+        let collider = world
+            .spawn((
+                RigidBody::Static,
+                Collider::rectangle(collider_width, collider_height),
+                CollisionLayers::new(config.layer, [GameLayer::Interactables, GameLayer::Player]),
+                Transform::from_xyz(
+                    corner.x + (collider_width / 2.0) - (tileset.tile_width as f32 / 2.0),
+                    corner.y + (collider_height / -2.0) + (tileset.tile_height as f32 / 2.0),
+                    config.z + 0.1,
+                ),
+            ))
+            .id();
+        if config.occluders {
+            // occluders take the shape of their mesh, and without a mask image the whole of it
+            // casts shadows
+            let mesh = world
+                .resource_mut::<Assets<Mesh>>()
+                .add(Rectangle::new(collider_width, collider_height));
+            let occluder = world.spawn((Mesh2d(mesh), LightOccluder2d::default())).id();
+            world.entity_mut(collider).add_child(occluder);
+        }
+        world.entity_mut(tilemap).add_child(collider);
+        collider
+    }
+}
+
+fn has_collider(sprite: &Option<TileSprite>) -> bool {
+    sprite.as_ref().is_some_and(|s| s.collider)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::map::special::starter_room::starter_room;
+    use crate::map::tileset::tuesday_tileset;
+    use crate::map::tuesday::TuesdayTile;
+    use crate::map::wall_wrap::wrap_walls;
+
+    fn test_world() -> (World, Handle<Tileset>) {
+        let mut world = World::new();
+        world.insert_resource(RngSeed(1));
+        world.init_resource::<Events<RenderedTileLayer>>();
+        world.init_resource::<Assets<Mesh>>();
+        world.init_resource::<Assets<ColorMaterial>>();
+        let mut tilesets = Assets::<Tileset>::default();
+        let handle = tilesets.add(tuesday_tileset());
+        world.insert_resource(tilesets);
+        (world, handle)
+    }
+
+    fn tile_count(world: &mut World) -> usize {
+        world.query::<&Tile>().iter(world).count()
+    }
+
+    /// How many tiles are drawn as part of a chunk
+    fn chunked_count(world: &mut World) -> usize {
+        let meshes = world
+            .query_filtered::<&Mesh2d, With<TileChunk>>()
+            .iter(world)
+            .map(|mesh| mesh.0.clone())
+            .collect::<Vec<_>>();
+        let assets = world.resource::<Assets<Mesh>>();
+        meshes
+            .iter()
+            .map(|mesh| assets.get(mesh).unwrap().count_vertices() / 4)
+            .sum()
+    }
+
+    #[test]
+    fn render_and_patch() {
+        let (mut world, tileset) = test_world();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let grid = wrap_walls(starter_room().grid, &mut rng);
+        let config = TileLayerConfig {
+            tileset,
+            occluders: true,
+            ..default()
+        };
+        RenderTileGrid::new(grid.clone(), 0, config.clone()).apply(&mut world);
+
+        let (entity, tilemap) = world.query::<(Entity, &Tilemap)>().single(&world);
+        assert_eq!(tilemap.width as usize, grid.width());
+        let colliders = tilemap.colliders.len();
+        let walls = tilemap.sprites.iter().flatten().flatten();
+        assert!(walls.filter(|s| s.collider).count() > 0);
+        assert_occluders(&mut world, colliders);
+        let expected = grid.iter().flatten().filter(|s| s[0].is_some()).count();
+        // walls and floor don't do anything so they're all part of the one chunk
+        assert_eq!(world.query::<&TileChunk>().iter(&world).count(), 1);
+        assert_eq!(chunked_count(&mut world), expected);
+        assert_eq!(tile_count(&mut world), 0);
+        assert_eq!(world.resource::<Events<RenderedTileLayer>>().len(), 1);
+
+        // knock a hole in the top wall
+        PatchTilemap {
+            tilemap: entity,
+            tiles: vec![(4, 0, Some(TuesdayTile::Floor)), (5, 0, None)],
+        }
+        .apply(&mut world);
+        assert_eq!(chunked_count(&mut world), expected - 1);
+        let tilemap = world.get::<Tilemap>(entity).unwrap();
+        assert_eq!(tilemap.sprites[4][0].as_ref().map(|s| s.index), Some(12));
+        assert!(tilemap.sprites[5][0].is_none());
+        assert!(tilemap.colliders.len() >= colliders);
+
+        // switches need their own entity to be flipped
+        PatchTilemap {
+            tilemap: entity,
+            tiles: vec![(4, 0, Some(TuesdayTile::SwitchLeft(7)))],
+        }
+        .apply(&mut world);
+        assert_eq!(chunked_count(&mut world), expected - 2);
+        let tilemap = world.get::<Tilemap>(entity).unwrap();
+        let tile = world.get::<Tile>(tilemap.tiles[4][0].unwrap()).unwrap();
+        assert_eq!((tile.grid_x, tile.grid_y), (4, 0));
+        assert_eq!(tile.role, Some(TileRole::Switch(7, false)));
+
+        // drawing into the same tilemap replaces what was there
+        RenderTileGrid::new(grid, 0, config)
+            .into_tilemap(entity)
+            .apply(&mut world);
+        assert_eq!(chunked_count(&mut world), expected);
+        assert_eq!(tile_count(&mut world), 0);
+        assert_eq!(world.query::<&Tilemap>().iter(&world).count(), 1);
+        assert_eq!(world.query::<&TileChunk>().iter(&world).count(), 1);
+    }
+
+    /// Every collider has one occluder, and there are no others
+    fn assert_occluders(world: &mut World, colliders: usize) {
+        let mut occluders = world.query_filtered::<&Parent, With<LightOccluder2d>>();
+        let parents = occluders.iter(world).map(|p| p.get()).collect::<Vec<_>>();
+        assert_eq!(parents.len(), colliders);
+        for parent in parents {
+            assert!(world.get::<Collider>(parent).is_some());
+        }
     }
 
-    colliders
+    /// Every tile with a collider is covered by exactly one collider rectangle
+    fn assert_colliders_cover(tilemap: &Tilemap) {
+        for (x, col) in tilemap.sprites.iter().enumerate() {
+            for (y, sprite) in col.iter().enumerate() {
+                let covering = tilemap
+                    .colliders
+                    .iter()
+                    .filter(|(rect, _)| rect.contains(x, y))
+                    .count();
+                assert_eq!(covering, has_collider(sprite) as usize, "{x},{y}");
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_colliders() {
+        let (mut world, tileset) = test_world();
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let grid = wrap_walls(starter_room().grid, &mut rng);
+        let config = TileLayerConfig {
+            tileset,
+            occluders: true,
+            ..default()
+        };
+        RenderTileGrid::new(grid, 0, config).apply(&mut world);
+        let (entity, tilemap) = world.query::<(Entity, &Tilemap)>().single(&world);
+        assert_colliders_cover(tilemap);
+        let before = tilemap.colliders.clone();
+        assert_occluders(&mut world, before.len());
+
+        // open a gap in the left wall
+        SetTileColliders {
+            tilemap: entity,
+            tiles: vec![(0, 4, false), (0, 5, false)],
+        }
+        .apply(&mut world);
+        let tilemap = world.get::<Tilemap>(entity).unwrap();
+        assert_colliders_cover(tilemap);
+        assert!(!has_collider(&tilemap.sprites[0][4]));
+        // only the rectangles that covered the gap were replaced
+        let untouched =
+            |(rect, _): &&(ColliderRect, Entity)| !rect.contains(0, 4) && !rect.contains(0, 5);
+        for collider in before.iter().filter(untouched) {
+            assert!(tilemap.colliders.contains(collider));
+        }
+        let replaced = before.iter().filter(|c| !untouched(c)).count();
+        assert_eq!(tilemap.colliders.len(), before.len() - replaced + 2);
+        let spawned = world.query::<&Collider>().iter(&world).count();
+        assert_eq!(
+            spawned,
+            world.get::<Tilemap>(entity).unwrap().colliders.len()
+        );
+        assert_occluders(&mut world, spawned);
+
+        // and close it again through the event
+        world.init_resource::<Events<SetTileCollider>>();
+        for grid_y in [4, 5] {
+            world.send_event(SetTileCollider {
+                tilemap: entity,
+                grid_x: 0,
+                grid_y,
+                collider: true,
+            });
+        }
+        world.run_system_once(update_tile_colliders).unwrap();
+        world.flush();
+        let tilemap = world.get::<Tilemap>(entity).unwrap();
+        assert_colliders_cover(tilemap);
+        assert!(has_collider(&tilemap.sprites[0][4]));
+        let spawned = world.query::<&Collider>().iter(&world).count();
+        assert_eq!(
+            spawned,
+            world.get::<Tilemap>(entity).unwrap().colliders.len()
+        );
+        assert_occluders(&mut world, spawned);
+    }
+
+    #[test]
+    fn large_map_entities() {
+        let (mut world, tileset) = test_world();
+        let mut grid = TileGrid::empty(200, 200, 1);
+        for x in 0..200 {
+            for y in 0..200 {
+                grid[x][y][0] = Some(TuesdayTile::Floor);
+            }
+        }
+        grid[100][100][0] = Some(TuesdayTile::PanelDisabled(1));
+        let config = TileLayerConfig {
+            tileset,
+            ..default()
+        };
+        RenderTileGrid::new(grid, 0, config).apply(&mut world);
+
+        // 13x13 chunks, one panel and the tilemap itself
+        assert_eq!(world.entities().len(), 13 * 13 + 2);
+        assert_eq!(tile_count(&mut world), 1);
+        assert_eq!(chunked_count(&mut world), 200 * 200 - 1);
+    }
+
+    #[test]
+    fn missing_tileset() {
+        let (mut world, _) = test_world();
+        let grid = starter_room().grid;
+        RenderTileGrid::new(grid, 0, TileLayerConfig::default()).apply(&mut world);
+        assert_eq!(world.query::<&Tilemap>().iter(&world).count(), 0);
+        assert_eq!(tile_count(&mut world), 0);
+    }
 }
@@ -1,45 +1,520 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, LoadContext};
 use bevy::prelude::*;
+use rand::prelude::*;
+use serde::Deserialize;
+use tilegen::TileGrid;
 
+use super::functional_tiles::UtilityTile;
+use super::plugin::{DoorKind, GateKind, TileRole, TileSprite};
 use super::tuesday::TuesdayTile;
 
-#[derive(Asset, Reflect, PartialEq, Eq, Clone, Debug)]
+/// Tilesets that are loaded at startup, by name
+const TILESETS: &[(&str, &str)] = &[
+    (TuesdayTile::name(), "tilesets/tuesday.tileset.ron"),
+    ("reactor", "tilesets/reactor.tileset.ron"),
+];
+
+#[derive(Asset, TypePath, Clone, Debug)]
 pub struct Tileset {
     pub tile_width: u8,
     pub tile_height: u8,
+    /// Size of the atlas in tiles
+    pub columns: u32,
+    pub rows: u32,
     pub image: Handle<Image>,
     pub layout: Handle<TextureAtlasLayout>,
+    pub tiles: HashMap<String, TileDef>,
+    /// Tiles to use for generic utility tiles when a map is drawn with this tileset
+    pub utility: HashMap<UtilityTile, TuesdayTile>,
+    /// Background light while the player is in a room drawn with this tileset
+    pub ambient: Option<TileAmbient>,
+}
+
+/// How one named tile is drawn and what it does
+#[derive(Debug, Clone, Deserialize)]
+pub struct TileDef {
+    /// Index of the sprite in the atlas
+    pub index: usize,
+    /// Whether the player is blocked by this tile
+    #[serde(default)]
+    pub collider: bool,
+    #[serde(default)]
+    pub role: Option<TileRoleDef>,
+    #[serde(default)]
+    pub light: Option<TileLight>,
+    /// Other sprites which are sometimes drawn instead, as index and chance
+    #[serde(default)]
+    pub alternates: Vec<(usize, f32)>,
+}
+
+/// The role a tile gets when it's spawned. Ids come from the tile in the grid
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum TileRoleDef {
+    Switch {
+        on: bool,
+    },
+    Door {
+        open: bool,
+        #[serde(default)]
+        kind: DoorKind,
+    },
+    DoorPanel,
+    PlayerStart,
+    Exit,
+    Keycard,
+    Gate {
+        kind: GateKind,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TileLight {
+    pub color: (u8, u8, u8),
+    pub radius: f32,
+    pub intensity: f32,
+    pub falloff: f32,
+    #[serde(default)]
+    pub animation: Option<LightAnimation>,
+}
+
+/// How a tile's light changes over time. `min` is the dimmest it gets as a fraction of its intensity
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum LightAnimation {
+    /// Drops to `min` at random, `rate` times a second, like a failing tube
+    Flicker { rate: f32, min: f32 },
+    /// Fades down to `min` and back up every `period` seconds
+    Pulse { period: f32, min: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct TileAmbient {
+    pub color: (u8, u8, u8),
+    pub brightness: f32,
+}
+
+/// Tiles that are drawn by looking them up in a tileset by name
+pub trait NamedTile {
+    fn tile_name(&self) -> String;
+    /// Id of the door, switch or panel this tile is part of
+    fn link_id(&self) -> u8;
+}
+
+impl TileRoleDef {
+    pub fn role(&self, id: u8) -> TileRole {
+        match self {
+            TileRoleDef::Switch { on } => TileRole::Switch(id, *on),
+            TileRoleDef::Door { open, kind } => TileRole::Door(id, *open, *kind),
+            TileRoleDef::Keycard => TileRole::Keycard(id),
+            TileRoleDef::Gate { kind } => TileRole::Gate(id, *kind),
+            TileRoleDef::DoorPanel => TileRole::DoorPanel(id),
+            TileRoleDef::PlayerStart => TileRole::PlayerStart(id),
+            TileRoleDef::Exit => TileRole::Exit,
+        }
+    }
+}
+
+impl Tileset {
+    /// Atlas index of the tile with this name
+    pub fn index(&self, tile: &impl NamedTile) -> Option<usize> {
+        self.tiles.get(&tile.tile_name()).map(|def| def.index)
+    }
+
+    /// Texture coordinates of a sprite in the atlas image, as left, top, right, bottom
+    pub fn uv(&self, index: usize) -> [f32; 4] {
+        let column = (index as u32 % self.columns) as f32;
+        let row = (index as u32 / self.columns) as f32;
+        let columns = self.columns as f32;
+        let rows = self.rows as f32;
+        [
+            column / columns,
+            row / rows,
+            (column + 1.0) / columns,
+            (row + 1.0) / rows,
+        ]
+    }
+
+    /// The light drawn on top of the tile with this name, if it has one
+    pub fn light(&self, tile: &impl NamedTile) -> Option<TileLight> {
+        self.tiles.get(&tile.tile_name()).and_then(|def| def.light)
+    }
+
+    /// How a tile is drawn, picking one of its alternates at random
+    pub fn sprite(&self, tile: &impl NamedTile, rng: &mut impl Rng) -> Option<TileSprite> {
+        let name = tile.tile_name();
+        let Some(def) = self.tiles.get(&name) else {
+            warn!("{name} is not in the tileset");
+            return None;
+        };
+        Some(TileSprite {
+            index: pick_alternate(def, rng),
+            collider: def.collider,
+            role: def.role.map(|r| r.role(tile.link_id())),
+            light: def.light,
+        })
+    }
+
+    /// Look up every tile of a grid layer in this tileset
+    pub fn layer_to_tile_sprites<T: NamedTile + Copy + Eq>(
+        &self,
+        grid: &TileGrid<T>,
+        layer: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<Vec<Option<TileSprite>>> {
+        let width = grid.width();
+        let height = grid.height();
+        let mut tilesprites: Vec<Vec<Option<TileSprite>>> = vec![vec![]; width];
+
+        for x in 0..width {
+            for y in 0..height {
+                let sprite = grid[x][y][layer].and_then(|tile| self.sprite(&tile, rng));
+                tilesprites[x].push(sprite);
+            }
+        }
+
+        tilesprites
+    }
+}
+
+fn pick_alternate(def: &TileDef, rng: &mut impl Rng) -> usize {
+    if def.alternates.is_empty() {
+        return def.index;
+    }
+    let roll = rng.random::<f32>();
+    let mut chance = 0.0;
+    for (index, alt_chance) in &def.alternates {
+        chance += alt_chance;
+        if roll < chance {
+            return *index;
+        }
+    }
+    def.index
+}
+
+/// A tileset as it's written in a `.tileset.ron` file
+#[derive(Debug, Deserialize)]
+pub struct TilesetFile {
+    /// Another tileset file whose tiles are used for any not listed in this one
+    #[serde(default)]
+    pub base: Option<String>,
+    /// Path to the atlas image in the assets folder
+    pub image: String,
+    pub tile_width: u8,
+    pub tile_height: u8,
+    pub columns: u32,
+    pub rows: u32,
+    pub tiles: HashMap<String, TileDef>,
+    #[serde(default)]
+    pub utility: HashMap<UtilityTile, TuesdayTile>,
+    #[serde(default)]
+    pub ambient: Option<TileAmbient>,
 }
 
-#[derive(Component, Debug)]
-pub struct TilesetId {
-    pub id: AssetId<Tileset>,
+#[derive(Debug)]
+pub enum TilesetError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    /// A tile that points past the end of the atlas
+    IndexOutOfRange {
+        name: String,
+        index: usize,
+    },
 }
 
-impl TilesetId {
-    pub fn new(id: AssetId<Tileset>) -> Self {
-        Self { id }
+impl Display for TilesetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TilesetError::Io(err) => write!(f, "could not read tileset: {err}"),
+            TilesetError::Parse(err) => write!(f, "could not parse tileset: {err}"),
+            TilesetError::IndexOutOfRange { name, index } => {
+                write!(f, "{name} uses sprite {index} which is not in the atlas")
+            }
+        }
     }
 }
 
-pub fn init_tuesday_tileset(
-    asset_server: Res<AssetServer>,
-    mut texture_atlas_layouts: ResMut<Assets<TextureAtlasLayout>>,
-    mut tilemaps: ResMut<Assets<Tileset>>,
-    mut commands: Commands,
-) {
-    let layout = texture_atlas_layouts.add(TextureAtlasLayout::from_grid(
-        UVec2::splat(32),
-        11,
-        6,
-        None,
-        None,
-    ));
-    let h = tilemaps.add(Tileset {
-        tile_width: 32,
-        tile_height: 32,
-        image: asset_server.load("custom.png"),
-        layout,
-    });
+impl std::error::Error for TilesetError {}
+
+impl From<std::io::Error> for TilesetError {
+    fn from(err: std::io::Error) -> Self {
+        TilesetError::Io(err)
+    }
+}
+
+impl From<ron::error::SpannedError> for TilesetError {
+    fn from(err: ron::error::SpannedError) -> Self {
+        TilesetError::Parse(err)
+    }
+}
 
-    commands.spawn((Name::new(TuesdayTile::name()), TilesetId::new(h.id())));
+impl TilesetFile {
+    pub fn from_ron(bytes: &[u8]) -> Result<Self, TilesetError> {
+        Ok(ron::de::from_bytes(bytes)?)
+    }
+
+    /// Fill in anything this tileset doesn't define from its base. Only the tiles are inherited,
+    /// the atlas always comes from this file
+    pub fn extend(&mut self, base: TilesetFile) {
+        for (name, def) in base.tiles {
+            self.tiles.entry(name).or_insert(def);
+        }
+        for (utility, tile) in base.utility {
+            self.utility.entry(utility).or_insert(tile);
+        }
+        self.ambient = self.ambient.or(base.ambient);
+    }
+
+    pub fn validate(&self) -> Result<(), TilesetError> {
+        let count = (self.columns * self.rows) as usize;
+        for (name, def) in &self.tiles {
+            let mut indexes =
+                std::iter::once(def.index).chain(def.alternates.iter().map(|(i, _)| *i));
+            if let Some(index) = indexes.find(|i| *i >= count) {
+                return Err(TilesetError::IndexOutOfRange {
+                    name: name.clone(),
+                    index,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn into_tileset(self, image: Handle<Image>, layout: Handle<TextureAtlasLayout>) -> Tileset {
+        Tileset {
+            tile_width: self.tile_width,
+            tile_height: self.tile_height,
+            columns: self.columns,
+            rows: self.rows,
+            image,
+            layout,
+            tiles: self.tiles,
+            utility: self.utility,
+            ambient: self.ambient,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct TilesetLoader;
+
+impl AssetLoader for TilesetLoader {
+    type Asset = Tileset;
+    type Settings = ();
+    type Error = TilesetError;
+
+    async fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &(),
+        load_context: &mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut file = TilesetFile::from_ron(&bytes)?;
+        if let Some(base) = file.base.take() {
+            let bytes = load_context
+                .read_asset_bytes(base)
+                .await
+                .map_err(std::io::Error::other)?;
+            file.extend(TilesetFile::from_ron(&bytes)?);
+        }
+        file.validate()?;
+
+        let layout = load_context.add_labeled_asset(
+            "layout".into(),
+            TextureAtlasLayout::from_grid(
+                UVec2::new(file.tile_width as u32, file.tile_height as u32),
+                file.columns,
+                file.rows,
+                None,
+                None,
+            ),
+        );
+        let image = load_context.load(&file.image);
+        Ok(file.into_tileset(image, layout))
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tileset.ron"]
+    }
+}
+
+/// Every tileset that has been loaded, by name
+#[derive(Resource, Default, Debug)]
+pub struct Tilesets {
+    handles: HashMap<String, Handle<Tileset>>,
+}
+
+impl Tilesets {
+    pub fn get(&self, name: &str) -> Option<Handle<Tileset>> {
+        self.handles.get(name).cloned()
+    }
+}
+
+/// The tileset with this name, from inside a command
+pub fn world_tileset<'w>(world: &'w World, name: &str) -> Option<(Handle<Tileset>, &'w Tileset)> {
+    let handle = world.resource::<Tilesets>().get(name)?;
+    let tileset = world.resource::<Assets<Tileset>>().get(&handle)?;
+    Some((handle, tileset))
+}
+
+pub fn load_tilesets(asset_server: Res<AssetServer>, mut tilesets: ResMut<Tilesets>) {
+    for (name, path) in TILESETS {
+        tilesets
+            .handles
+            .insert(name.to_string(), asset_server.load(*path));
+    }
+}
+
+/// Run condition for anything that needs tiles drawn, true once every tileset and its image has loaded
+pub fn tilesets_ready(tilesets: Res<Tilesets>, asset_server: Res<AssetServer>) -> bool {
+    !tilesets.handles.is_empty()
+        && tilesets
+            .handles
+            .values()
+            .all(|handle| asset_server.is_loaded_with_dependencies(handle))
+}
+
+/// The tuesday tileset without its images, for tests
+#[cfg(test)]
+pub fn tuesday_tileset() -> Tileset {
+    let file =
+        TilesetFile::from_ron(include_bytes!("../../assets/tilesets/tuesday.tileset.ron")).unwrap();
+    file.validate().unwrap();
+    file.into_tileset(Handle::default(), Handle::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::map::IsImpassable;
+    use crate::map::special::starter_room::starter_room;
+
+    #[test]
+    fn tuesday_tiles() {
+        let tileset = tuesday_tileset();
+        for (name, def) in &tileset.tiles {
+            // tiles with an id need one to parse
+            let one_way = name
+                .strip_prefix("DoorFrameOneWay")
+                .map(|heading| format!("DoorFrameOneWay(1, {heading})"));
+            let tile = ron::from_str::<TuesdayTile>(name)
+                .or_else(|_| ron::from_str::<TuesdayTile>(&format!("{name}(1)")))
+                .or_else(|err| one_way.as_deref().map_or(Err(err), ron::from_str))
+                .unwrap_or_else(|_| panic!("{name} is not a tuesday tile"));
+            assert_eq!(tile.tile_name(), *name);
+            assert_eq!(def.collider, tile.is_impassable(), "{name} collider");
+        }
+
+        let [left, top, right, bottom] = tileset.uv(tileset.index(&TuesdayTile::Floor).unwrap());
+        assert_eq!((left * 11.0, top * 6.0), (1.0, 1.0));
+        assert_eq!((right * 11.0, bottom * 6.0), (2.0, 2.0));
+
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let grid = starter_room().grid;
+        let sprites = tileset.layer_to_tile_sprites(&grid, 1, &mut rng);
+        assert_eq!(
+            sprites[6][2].as_ref().map(|s| s.role.clone()),
+            Some(Some(TileRole::DoorPanel(2)))
+        );
+        // every tile in the room is in the tileset
+        for z in 0..grid.depth() {
+            let sprites = tileset.layer_to_tile_sprites(&grid, z, &mut rng);
+            for x in 0..grid.width() {
+                for y in 0..grid.height() {
+                    assert_eq!(grid[x][y][z].is_some(), sprites[x][y].is_some());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn alternates() {
+        let mut def = TileDef {
+            index: 1,
+            collider: false,
+            role: None,
+            light: None,
+            alternates: vec![(2, 0.25), (3, 0.25)],
+        };
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let mut counts = [0; 4];
+        for _ in 0..1000 {
+            counts[pick_alternate(&def, &mut rng)] += 1;
+        }
+        assert!(counts[1] > 400 && counts[2] > 150 && counts[3] > 150);
+
+        def.alternates.clear();
+        assert_eq!(pick_alternate(&def, &mut rng), 1);
+
+        let file = TilesetFile::from_ron(
+            br#"(image: "a.png", tile_width: 8, tile_height: 8, columns: 2, rows: 2,
+                tiles: { "Floor": (index: 1, alternates: [(4, 0.5)]) })"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            file.validate(),
+            Err(TilesetError::IndexOutOfRange { index: 4, .. })
+        ));
+    }
+
+    #[test]
+    fn reactor_extends_tuesday() {
+        let mut reactor =
+            TilesetFile::from_ron(include_bytes!("../../assets/tilesets/reactor.tileset.ron"))
+                .unwrap();
+        assert_eq!(
+            reactor.base.as_deref(),
+            Some("tilesets/tuesday.tileset.ron")
+        );
+        let tuesday = tuesday_tileset();
+        reactor.extend(
+            TilesetFile::from_ron(include_bytes!("../../assets/tilesets/tuesday.tileset.ron"))
+                .unwrap(),
+        );
+        reactor.validate().unwrap();
+        let reactor = reactor.into_tileset(Handle::default(), Handle::default());
+
+        assert_eq!(reactor.tiles.len(), tuesday.tiles.len());
+        assert_eq!(reactor.index(&TuesdayTile::WallTop), Some(1));
+        assert_eq!(tuesday.index(&TuesdayTile::WallTop), Some(4));
+        assert_eq!(reactor.index(&TuesdayTile::Floor), Some(12));
+        assert_eq!(
+            reactor.utility.get(&UtilityTile::Wall),
+            Some(&TuesdayTile::WallPanelSingle)
+        );
+        assert!(tuesday.utility.is_empty());
+        // emergency lighting in the reactor, flickering lights and all
+        assert_ne!(reactor.ambient, tuesday.ambient);
+        assert!(tuesday.ambient.is_some());
+        let light = reactor.light(&TuesdayTile::Resoursce1).unwrap();
+        assert!(matches!(
+            light.animation,
+            Some(LightAnimation::Flicker { .. })
+        ));
+        assert_eq!(
+            tuesday.light(&TuesdayTile::Resoursce1).unwrap().animation,
+            None
+        );
+        // lights belong to tiles rather than sprites, which the keycard shares with the exit
+        assert_eq!(
+            tuesday.index(&TuesdayTile::Keycard(1)),
+            tuesday.index(&TuesdayTile::Exit)
+        );
+        assert!(tuesday.light(&TuesdayTile::Keycard(1)).is_some());
+        assert!(tuesday.light(&TuesdayTile::Exit).is_none());
+        // walls still block the player when they're drawn differently
+        for (name, def) in &reactor.tiles {
+            assert_eq!(
+                def.collider, tuesday.tiles[name].collider,
+                "{name} collider"
+            );
+        }
+    }
 }
@@ -1,9 +1,40 @@
+use std::collections::HashMap;
+
 use rand::prelude::*;
+use tilegen::TileGrid;
 
 use crate::map::functional_tiles::UtilityTile;
 
 use super::TuesdayTile;
 
+/// Translate every tile of a utility grid into Cosmic Legacy tiles. A tileset can swap in its own
+/// tiles for any utility tile
+pub fn utility_grid_to_tuesday(
+    grid: &TileGrid<UtilityTile>,
+    biome: &HashMap<UtilityTile, TuesdayTile>,
+    rng: &mut impl Rng,
+) -> TileGrid<TuesdayTile> {
+    TileGrid::new(
+        grid.iter()
+            .map(|col| {
+                col.iter()
+                    .map(|stack| {
+                        stack
+                            .iter()
+                            .map(|tile| {
+                                tile.map(|t| match biome.get(&t) {
+                                    Some(tile) => *tile,
+                                    None => utility_to_tuesday(t, rng),
+                                })
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
 /// Translate the generic utility tiles into Cosmic Legacy tiles
 pub fn utility_to_tuesday(utility: UtilityTile, _rng: &mut impl Rng) -> TuesdayTile {
     match utility {
@@ -44,3 +75,30 @@ pub fn utility_to_tuesday(utility: UtilityTile, _rng: &mut impl Rng) -> TuesdayT
         _ => TuesdayTile::Test,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn translate_grid() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let mut grid = TileGrid::empty(2, 1, 2);
+        grid[0][0][0] = Some(UtilityTile::Floor);
+        grid[1][0][1] = Some(UtilityTile::WallBorderTop);
+
+        let translated = utility_grid_to_tuesday(&grid, &HashMap::new(), &mut rng);
+        assert_eq!(translated[0][0][0], Some(TuesdayTile::Floor));
+        assert_eq!(translated[0][0][1], None);
+        assert_eq!(translated[1][0][0], None);
+        assert_eq!(translated[1][0][1], Some(TuesdayTile::WallTop));
+
+        let biome = HashMap::from([(UtilityTile::Floor, TuesdayTile::FloorAlt1)]);
+        let translated = utility_grid_to_tuesday(&grid, &biome, &mut rng);
+        assert_eq!(translated[0][0][0], Some(TuesdayTile::FloorAlt1));
+        assert_eq!(translated[1][0][1], Some(TuesdayTile::WallTop));
+    }
+}
@@ -1,183 +1,232 @@
+use std::collections::HashSet;
+use std::sync::LazyLock;
+
 use bevy::prelude::*;
-use tilegen::TileGrid;
+use serde::Deserialize;
 
-use crate::map::{IsImpassable, TileRole, TileSprite};
+use crate::map::tileset::{NamedTile, TilesetFile};
+use crate::map::{GateKind, Heading, IsImpassable};
 
-#[derive(Component, Copy, Clone, Default, Debug, PartialEq, Eq, Hash)]
+#[derive(Component, Copy, Clone, Default, Debug, PartialEq, Eq, Hash, Deserialize)]
 #[allow(unused)]
-#[repr(u32)]
 pub enum TuesdayTile {
     // row 1
-    WallTopLeftCaution = 0,
-    WallTopCaution = 1,
-    WallTopRightCaution = 2,
-    WallTopLeft = 3,
-    WallTop = 4,
-    WallTopRight = 5,
-    FloorAlt1 = 6,
-    FloorAlt2 = 7,
-    WallInnerCornerBottomRight = 8,
-    WallInnerCornerBottomLeft = 9,
-    Resoursce1 = 10,
+    WallTopLeftCaution,
+    WallTopCaution,
+    WallTopRightCaution,
+    WallTopLeft,
+    WallTop,
+    WallTopRight,
+    FloorAlt1,
+    FloorAlt2,
+    WallInnerCornerBottomRight,
+    WallInnerCornerBottomLeft,
+    Resoursce1,
 
     // row 2
-    WallLeftCaution = 11,
-    Floor = 12,
-    WallRightCaution = 13,
-    WallLeft = 14,
-    FloorAlt3 = 15,
-    WallRight = 16,
-    WallPanelMiddleAlt1 = 17,
-    WallPanelMiddleALt2 = 18,
-    WallInnerCornerTopLeft = 19,
-    WallInnerCornerTopRight = 20,
-    SwitchSelectedLeft = 21,
+    WallLeftCaution,
+    Floor,
+    WallRightCaution,
+    WallLeft,
+    FloorAlt3,
+    WallRight,
+    WallPanelMiddleAlt1,
+    WallPanelMiddleALt2,
+    WallInnerCornerTopLeft,
+    WallInnerCornerTopRight,
+    SwitchSelectedLeft,
 
     // row 3
-    WallBottomLeftCaution = 22,
-    WallBottomCaution = 23,
-    WallBottomRightCaution = 24,
-    WallBottomLeft = 25,
-    WallBottom = 26,
-    WallBottomRight = 27,
-    WallInnerCornerBottomRightCaution = 28,
-    WallInnerCornerBottomLeftCaution = 29,
-    WallInnerCornerTopLeftCaution = 30,
-    WallInnerCornerTopRightCaution = 31,
-    SwitchSelectedRight = 32,
+    WallBottomLeftCaution,
+    WallBottomCaution,
+    WallBottomRightCaution,
+    WallBottomLeft,
+    WallBottom,
+    WallBottomRight,
+    WallInnerCornerBottomRightCaution,
+    WallInnerCornerBottomLeftCaution,
+    WallInnerCornerTopLeftCaution,
+    WallInnerCornerTopRightCaution,
+    SwitchSelectedRight,
 
     // row 4
-    EmptyDecoration1 = 33,
-    EmptyDecoration2 = 34,
-    DoorFrame(u8) = 35,
-    PanelDisabled(u8) = 36,
-    PanelEnabled(u8) = 37,
-    WallPanelLeft = 38,
-    WallPanelMiddle = 39,
-    WallPanelRight = 40,
-    SwitchLeft(u8) = 41,
-    SwitchRight(u8) = 42,
+    EmptyDecoration1,
+    EmptyDecoration2,
+    DoorFrame(u8),
+    PanelDisabled(u8),
+    PanelEnabled(u8),
+    WallPanelLeft,
+    WallPanelMiddle,
+    WallPanelRight,
+    SwitchLeft(u8),
+    SwitchRight(u8),
     #[default]
-    Test = 43,
+    Test,
 
     // row 5
-    WallDoubleLeftCorner = 44,
-    WallDoubleRightCorner = 45,
-    WallAllCorner = 46,
-    WallDoubleHorizontal = 47,
-    WallDoubleVertical = 48,
-    WallPanelSingle = 49,
-    WallDoubleCornerTop = 50,
-    WallDoubleCornerBottom = 51,
-    WallDoubleUpper = 52,
-    WallDoubleLower = 53,
-    Transparent = 54,
+    WallDoubleLeftCorner,
+    WallDoubleRightCorner,
+    WallAllCorner,
+    WallDoubleHorizontal,
+    WallDoubleVertical,
+    WallPanelSingle,
+    WallDoubleCornerTop,
+    WallDoubleCornerBottom,
+    WallDoubleUpper,
+    WallDoubleLower,
+    Transparent,
 
     // row 6
-    PanelDisabledSelected = 55,
-    PanelEnabledSelected = 56,
-    Power = 57,
-    PowerSelected = 58,
-
-    PlayerStart(u8) = 999,
+    PanelDisabledSelected,
+    PanelEnabledSelected,
+    Power,
+    PowerSelected,
+
+    Exit,
+    PlayerStart(u8),
+    /// A door which is open until it's powered
+    DoorFrameOpen(u8),
+    /// A door which closes again a few seconds after it opens
+    DoorFrameTimed(u8),
+    /// A door which only lets the player through going one way
+    DoorFrameOneWay(u8, Heading),
+    /// A door which opens for the player once they're carrying its keycard
+    DoorFrameKeycard(u8),
+    /// A door which needs every link into it powered
+    DoorFrameAll(u8),
+    /// Opens the keycard door with the same id
+    Keycard(u8),
+    /// Logic gates, which pass along a combination of everything wired into them
+    GateAnd(u8),
+    GateOr(u8),
+    GateXor(u8),
+    GateNot(u8),
+    GateMux(u8),
 }
 
-impl Into<u32> for TuesdayTile {
-    fn into(self) -> u32 {
-        // https://doc.rust-lang.org/reference/items/enumerations.html#casting
-        unsafe { *(&self as *const Self as *const u32) }
-    }
-}
+/// Tiles with a collider in the tuesday tileset, so walls are only ever listed in the one place
+static COLLIDERS: LazyLock<HashSet<TuesdayTile>> = LazyLock::new(|| {
+    let file = TilesetFile::from_ron(include_bytes!(
+        "../../../assets/tilesets/tuesday.tileset.ron"
+    ))
+    .expect("the tuesday tileset should parse");
+    file.tiles
+        .into_iter()
+        .filter(|(_, def)| def.collider)
+        .map(|(name, _)| {
+            ron::from_str::<TuesdayTile>(&name)
+                .unwrap_or_else(|_| panic!("{name} has a collider but is not a plain tuesday tile"))
+        })
+        .collect()
+});
 
-impl Into<usize> for TuesdayTile {
-    fn into(self) -> usize {
-        let you32: u32 = self.into();
-        you32 as usize
+impl IsImpassable for TuesdayTile {
+    fn is_impassable(&self) -> bool {
+        COLLIDERS.contains(self)
     }
 }
 
-impl PartialEq<usize> for TuesdayTile {
-    fn eq(&self, other: &usize) -> bool {
-        let tile_index: usize = (*self).into();
-        &tile_index == other
+impl NamedTile for TuesdayTile {
+    fn tile_name(&self) -> String {
+        // each way a one way door faces is drawn as its own tile
+        if let Self::DoorFrameOneWay(_, heading) = self {
+            return format!("DoorFrameOneWay{heading:?}");
+        }
+        // the variant name without the id
+        let name = format!("{self:?}");
+        match name.split_once('(') {
+            Some((name, _)) => name.to_string(),
+            None => name,
+        }
     }
-}
 
-impl IsImpassable for TuesdayTile {
-    fn is_impassable(&self) -> bool {
-        *self == Self::WallBottom
-            || *self == Self::WallBottomLeft
-            || *self == Self::WallBottomRight
-            || *self == Self::WallAllCorner
-            || *self == Self::WallDoubleRightCorner
-            || *self == Self::WallDoubleLeftCorner
-            || *self == Self::WallDoubleUpper
-            || *self == Self::WallDoubleLower
-            || *self == Self::WallDoubleVertical
-            || *self == Self::WallDoubleHorizontal
-            || *self == Self::WallInnerCornerBottomLeft
-            || *self == Self::WallInnerCornerBottomRight
-            || *self == Self::WallInnerCornerTopLeft
-            || *self == Self::WallInnerCornerTopRight
-            || *self == Self::WallLeft
-            || *self == Self::WallPanelLeft
-            || *self == Self::WallPanelMiddle
-            || *self == Self::WallPanelMiddleALt2
-            || *self == Self::WallPanelMiddleAlt1
-            || *self == Self::WallPanelRight
-            || *self == Self::WallPanelSingle
-            || *self == Self::WallRight
-            || *self == Self::WallTop
-            || *self == Self::WallTopLeft
-            || *self == Self::WallTopRight
-            || *self == Self::WallDoubleCornerBottom
-            || *self == Self::WallDoubleCornerTop
+    fn link_id(&self) -> u8 {
+        match self {
+            Self::DoorFrame(id)
+            | Self::DoorFrameOpen(id)
+            | Self::DoorFrameTimed(id)
+            | Self::DoorFrameOneWay(id, _)
+            | Self::DoorFrameKeycard(id)
+            | Self::DoorFrameAll(id)
+            | Self::Keycard(id)
+            | Self::GateAnd(id)
+            | Self::GateOr(id)
+            | Self::GateXor(id)
+            | Self::GateNot(id)
+            | Self::GateMux(id)
+            | Self::PanelDisabled(id)
+            | Self::PanelEnabled(id)
+            | Self::SwitchLeft(id)
+            | Self::SwitchRight(id)
+            | Self::PlayerStart(id) => *id,
+            _ => 0,
+        }
     }
 }
 
 impl TuesdayTile {
     #[inline]
-    pub fn name() -> &'static str {
+    pub const fn name() -> &'static str {
         NAME
     }
 
-    pub fn layer_to_tile_sprites(
-        grid: &TileGrid<Self>,
-        layer: usize,
-    ) -> Vec<Vec<Option<TileSprite>>> {
-        let width = grid.width();
-        let height = grid.height();
-        let mut tilesprites: Vec<Vec<Option<TileSprite>>> = vec![vec![]; width];
-
-        for x in 0..width {
-            for y in 0..height {
-                if let Some(tile) = grid[x][y][layer] {
-                    let role = match tile {
-                        Self::DoorFrame(id) => Some(TileRole::Door(id)),
-                        Self::SwitchLeft(id) => Some(TileRole::Switch(id, false)),
-                        Self::SwitchRight(id) => Some(TileRole::Switch(id, true)),
-                        Self::PlayerStart(id) => Some(TileRole::PlayerStart(id)),
-                        Self::PanelDisabled(id) => Some(TileRole::DoorPanel(id)),
-                        Self::PanelEnabled(id) => Some(TileRole::DoorPanel(id)),
-                        _ => None,
-                    };
-                    let index: usize = match tile {
-                        Self::PlayerStart(_) => Self::Transparent.into(),
-                        t => t.into(),
-                    };
-
-                    tilesprites[x].push(Some(TileSprite {
-                        index,
-                        collider: Self::is_impassable(&tile),
-                        role,
-                    }));
-                } else {
-                    tilesprites[x].push(None);
-                }
-            }
+    /// Id of the door if this is any kind of door frame
+    pub fn door_id(&self) -> Option<u8> {
+        match self {
+            Self::DoorFrame(id)
+            | Self::DoorFrameOpen(id)
+            | Self::DoorFrameTimed(id)
+            | Self::DoorFrameOneWay(id, _)
+            | Self::DoorFrameKeycard(id)
+            | Self::DoorFrameAll(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// The same tile wired up under a different id, if it's a door, switch, panel, keycard or gate
+    pub fn with_link_id(&self, id: u8) -> Option<Self> {
+        match *self {
+            Self::DoorFrame(_) => Some(Self::DoorFrame(id)),
+            Self::DoorFrameOpen(_) => Some(Self::DoorFrameOpen(id)),
+            Self::DoorFrameTimed(_) => Some(Self::DoorFrameTimed(id)),
+            Self::DoorFrameOneWay(_, heading) => Some(Self::DoorFrameOneWay(id, heading)),
+            Self::DoorFrameKeycard(_) => Some(Self::DoorFrameKeycard(id)),
+            Self::DoorFrameAll(_) => Some(Self::DoorFrameAll(id)),
+            Self::Keycard(_) => Some(Self::Keycard(id)),
+            Self::PanelDisabled(_) => Some(Self::PanelDisabled(id)),
+            Self::PanelEnabled(_) => Some(Self::PanelEnabled(id)),
+            Self::SwitchLeft(_) => Some(Self::SwitchLeft(id)),
+            Self::SwitchRight(_) => Some(Self::SwitchRight(id)),
+            Self::GateAnd(_) => Some(Self::GateAnd(id)),
+            Self::GateOr(_) => Some(Self::GateOr(id)),
+            Self::GateXor(_) => Some(Self::GateXor(id)),
+            Self::GateNot(_) => Some(Self::GateNot(id)),
+            Self::GateMux(_) => Some(Self::GateMux(id)),
+            _ => None,
         }
+    }
 
-        tilesprites
+    /// Id and kind of the gate if this is a logic gate
+    pub fn gate(&self) -> Option<(u8, GateKind)> {
+        match self {
+            Self::GateAnd(id) => Some((*id, GateKind::And)),
+            Self::GateOr(id) => Some((*id, GateKind::Or)),
+            Self::GateXor(id) => Some((*id, GateKind::Xor)),
+            Self::GateNot(id) => Some((*id, GateKind::Not)),
+            Self::GateMux(id) => Some((*id, GateKind::Mux)),
+            _ => None,
+        }
+    }
+
+    /// The logic gate tile for a gate of this kind
+    pub fn from_gate(id: u8, kind: GateKind) -> Self {
+        match kind {
+            GateKind::And => Self::GateAnd(id),
+            GateKind::Or => Self::GateOr(id),
+            GateKind::Xor => Self::GateXor(id),
+            GateKind::Not => Self::GateNot(id),
+            GateKind::Mux => Self::GateMux(id),
+        }
     }
 }
 
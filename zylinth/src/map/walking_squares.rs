@@ -7,17 +7,54 @@ use rand::Rng;
 use rand_chacha::ChaCha8Rng;
 use tilegen::*;
 
+/// Which way the walk prefers to go when it changes direction
+#[allow(unused)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WalkBias {
+    /// Every direction is equally likely
+    #[default]
+    None,
+    /// Prefer heading back towards the origin, keeping rooms clustered
+    TowardsOrigin,
+    /// Prefer heading away from the origin, stretching rooms across the map
+    AwayFromOrigin,
+    /// Prefer heading towards whichever quarter of the map has the fewest rooms
+    Unfilled,
+}
+
+/// Extra tuning for walking squares
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WalkOptions {
+    /// smallest and largest width of each room
+    pub room_width: (usize, usize),
+    /// smallest and largest height of each room
+    pub room_height: (usize, usize),
+    pub bias: WalkBias,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            room_width: (8, 14),
+            room_height: (8, 10),
+            bias: WalkBias::None,
+        }
+    }
+}
+
 pub fn walking_squares(
     total_width: usize,
     total_height: usize,
     target_density: f32,
     branch_factor: f32,
     wander_factor: f32,
+    options: &WalkOptions,
     rng: &mut ChaCha8Rng,
 ) -> TileGrid<UtilityTile> {
     let mut grid = TileGrid::empty(total_width, total_height, 1);
     let bounding = TileRect::new(0, 0, total_width, total_height);
     let origin_region = bounding.inner_slice(9, 9).unwrap();
+    let origin = origin_region.center();
 
     // starting region is in the top left-ish of the grid
     let mut previous_region = origin_region.clone();
@@ -32,12 +69,16 @@ pub fn walking_squares(
     let mut steps_since_last_dir_change = 0;
 
     while density < target_density && attempts < 5000 {
-        let starting_region = previous_region.inner_slice(3, 3);
+        // the origin is always usable, even when the map is too small to slice it down
+        let starting_region = previous_region
+            .inner_slice(3, 3)
+            .or_else(|| (previous_region == origin_region).then(|| origin_region.clone()));
 
         // if the starting region is unrealistic for placement, just return to origin
         if starting_region.is_none() {
             trace!("Returning to origin");
             return_to_origin += 1;
+            attempts += 1;
             previous_region = origin_region.clone();
             continue;
         }
@@ -50,7 +91,17 @@ pub fn walking_squares(
             starting_region
         );
 
-        let room = Room::gen_rect(&dir, &previous_region, 8, 14, 8, 10, rng);
+        let (min_width, max_width) = options.room_width;
+        let (min_height, max_height) = options.room_height;
+        let room = Room::gen_rect(
+            &dir,
+            &previous_region,
+            min_width,
+            max_width,
+            min_height,
+            max_height,
+            rng,
+        );
         let mut change_dir = false;
         match room.intersect(&bounding) {
             None => change_dir = true,
@@ -82,7 +133,7 @@ pub fn walking_squares(
         }
 
         if change_dir {
-            dir = TileDir::rnd_without(&dir, rng);
+            dir = next_dir(&dir, &previous_region, &origin, &grid, options.bias, rng);
             steps_since_last_dir_change = 0;
             trace!("Changing direction to {:?}", dir);
         }
@@ -97,30 +148,148 @@ pub fn walking_squares(
     grid
 }
 
-fn get_weighted_directions(room_rect: &TileRect, origin: &TilePoint) -> HashMap<TileDir, f32> {
+/// Pick a new direction to walk in, other than the current one
+fn next_dir(
+    dir: &TileDir,
+    room_rect: &TileRect,
+    origin: &TilePoint,
+    grid: &TileGrid<UtilityTile>,
+    bias: WalkBias,
+    rng: &mut ChaCha8Rng,
+) -> TileDir {
+    let mut weights = match bias {
+        WalkBias::None => return TileDir::rnd_without(dir, rng),
+        WalkBias::TowardsOrigin => get_weighted_directions(room_rect, origin, true),
+        WalkBias::AwayFromOrigin => get_weighted_directions(room_rect, origin, false),
+        WalkBias::Unfilled => get_weighted_directions(room_rect, &emptiest_quadrant(grid), true),
+    };
+    weights.remove(dir);
+    TileDir::rnd_weighted(&weights, rng)
+}
+
+/// Weight each direction by how far that side of the room is from the target point.
+/// Each direction gets half the weight of the one before it, starting with the nearest side when
+/// heading `towards` the target, otherwise starting with the farthest
+fn get_weighted_directions(
+    room_rect: &TileRect,
+    target: &TilePoint,
+    towards: bool,
+) -> HashMap<TileDir, f32> {
     let mut distances = TileDir::vec()
         .iter()
         .map(|dir| match *dir {
-            TileDir::Left => (*dir, room_rect.left_center().distance(origin)),
-            TileDir::Right => (*dir, room_rect.right_center().distance(origin)),
-            TileDir::Up => (*dir, room_rect.top_center().distance(origin)),
-            TileDir::Down => (*dir, room_rect.bottom_center().distance(origin)),
+            TileDir::Left => (*dir, room_rect.left_center().distance(target)),
+            TileDir::Right => (*dir, room_rect.right_center().distance(target)),
+            TileDir::Up => (*dir, room_rect.top_center().distance(target)),
+            TileDir::Down => (*dir, room_rect.bottom_center().distance(target)),
         })
         .collect::<Vec<_>>();
 
     // sort by nearest to farthest
     distances.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-    // order by farthest to nearest
-    let rev_distances = distances.iter().rev().collect::<Vec<_>>();
+    if !towards {
+        // order by farthest to nearest
+        distances.reverse();
+    }
 
     // TODO: maybe instead of weighting by distance, it could instead have equal weights if the
     // point is far enough from the origin
     let mut weight_budget = 1.0;
     let mut weights: HashMap<TileDir, f32> = HashMap::new();
-    for (dir, _) in rev_distances {
-        weights.insert(*dir, weight_budget / 2.0);
+    for (dir, _) in distances {
+        weights.insert(dir, weight_budget / 2.0);
         weight_budget /= 2.0;
     }
 
     weights
 }
+
+/// Get the center of the quarter of the grid with the least filled tiles
+fn emptiest_quadrant(grid: &TileGrid<UtilityTile>) -> TilePoint {
+    let half_width = grid.width() / 2;
+    let half_height = grid.height() / 2;
+    let quadrants = [
+        TileRect::new(0, 0, half_width, half_height),
+        TileRect::new(half_width, 0, grid.width(), half_height),
+        TileRect::new(0, half_height, half_width, grid.height()),
+        TileRect::new(half_width, half_height, grid.width(), grid.height()),
+    ];
+
+    let filled = |rect: &TileRect| {
+        let mut count = 0;
+        for x in rect.min.x..rect.max.x {
+            for y in rect.min.y..rect.max.y {
+                if grid[x][y][0].is_some() {
+                    count += 1;
+                }
+            }
+        }
+        count
+    };
+
+    quadrants
+        .iter()
+        .min_by_key(|rect| filled(rect))
+        .unwrap()
+        .center()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn fills_small_map() {
+        // the origin of a map this short is too small to slice, which used to loop forever
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let grid = walking_squares(50, 22, 0.125, 0.25, 0.5, &WalkOptions::default(), &mut rng);
+        assert!(measure_density(&grid, 0) >= 0.125);
+    }
+
+    #[test]
+    fn weighted_directions() {
+        let room = TileRect::new(10, 10, 14, 14);
+        let target = TilePoint::new(30, 12);
+
+        let towards = get_weighted_directions(&room, &target, true);
+        assert_eq!(towards[&TileDir::Right], 0.5);
+        assert_eq!(towards[&TileDir::Left], 0.0625);
+
+        let away = get_weighted_directions(&room, &target, false);
+        assert_eq!(away[&TileDir::Left], 0.5);
+        assert_eq!(away[&TileDir::Right], 0.0625);
+    }
+
+    #[test]
+    fn emptiest() {
+        let mut grid = TileGrid::empty(10, 10, 1);
+        for x in 0..10 {
+            for y in 0..5 {
+                grid[x][y][0] = Some(UtilityTile::Floor);
+            }
+        }
+        grid[1][6][0] = Some(UtilityTile::Floor);
+        assert_eq!(emptiest_quadrant(&grid), TilePoint::new(7, 7));
+    }
+
+    #[test]
+    fn biased_walks() {
+        let biases = [
+            WalkBias::TowardsOrigin,
+            WalkBias::AwayFromOrigin,
+            WalkBias::Unfilled,
+        ];
+        for bias in biases {
+            let mut rng = ChaCha8Rng::seed_from_u64(1);
+            let options = WalkOptions {
+                room_width: (4, 6),
+                room_height: (4, 6),
+                bias,
+            };
+            let grid = walking_squares(60, 40, 0.3, 0.25, 0.5, &options, &mut rng);
+            assert!(measure_density(&grid, 0) >= 0.3, "{:?}", bias);
+        }
+    }
+}
@@ -188,3 +188,60 @@ lazy_static! {
         },
     ];
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::map::special::starter_room::starter_room;
+    use crate::map::tileset::tuesday_tileset;
+
+    /// Atlas indices of the starter room's base layer after wrapping, one row per line. `.` is empty
+    const WRAPPED_STARTER_ROOM: &str = "
+ 3  4  4  4  4  4  4  4  4  4  4  4  5
+14 39 39 39 39 39 39 39 39 39 39 39 16
+14 12 12 50 12 12 12 12 12 50 12 12 16
+14 12 12 51 12 12 12 12 12 51 12 12 16
+14 12 12 38 39 40 12 38 39 40 12 12 16
+14 12 12 12 12 12 12 12 12 12 12 12 16
+14 12 12 12 12 12 12 12 12 12 12 12 16
+14 12 12 12 12 12 12 12 12 12 12 12 16
+25 26 26 26 26 26 26 26 26 26 26 26 27
+";
+
+    fn base_layer_indices(grid: &TileGrid<TuesdayTile>) -> TileGrid<usize> {
+        let tileset = tuesday_tileset();
+        let mut indices = TileGrid::empty(grid.width(), grid.height(), 1);
+        for x in 0..grid.width() {
+            for y in 0..grid.height() {
+                indices[x][y][0] = grid[x][y][0].and_then(|t| tileset.index(&t));
+            }
+        }
+        indices
+    }
+
+    fn parse_indices(rows: &str) -> TileGrid<usize> {
+        let rows = rows
+            .trim()
+            .lines()
+            .map(|row| row.split_whitespace().collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let mut indices = TileGrid::empty(rows[0].len(), rows.len(), 1);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, index) in row.iter().enumerate() {
+                indices[x][y][0] = index.parse().ok();
+            }
+        }
+        indices
+    }
+
+    #[test]
+    fn wrap_starter_room() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let wrapped = base_layer_indices(&wrap_walls(starter_room().grid, &mut rng));
+        let diff = parse_indices(WRAPPED_STARTER_ROOM).diff(&wrapped);
+        assert!(diff.is_empty(), "wall wrapping output changed:\n{diff}");
+    }
+}
@@ -3,7 +3,7 @@ use bevy::prelude::*;
 
 use crate::connections::SourceStateChanged;
 use crate::defs::{ControlLink, ControlSource, ControlTarget, GameLayer};
-use crate::map::{Tile, TileRole, TuesdayTile};
+use crate::map::{Tile, TileRole, Tileset, TuesdayTile};
 use crate::selection::Selectable;
 
 #[derive(Component)]
@@ -55,12 +55,19 @@ fn panel_added(
 
 fn target_changed(
     mut panels: Query<
-        (&ControlTarget, &mut ControlSource, &mut Sprite, &Selectable),
+        (
+            &mut Tile,
+            &ControlTarget,
+            &mut ControlSource,
+            &mut Sprite,
+            &Selectable,
+        ),
         (With<DoorPanel>, Changed<ControlTarget>),
     >,
+    tilesets: Res<Assets<Tileset>>,
     mut ev_sourcestate: EventWriter<SourceStateChanged>,
 ) {
-    for (target, mut source, mut sprite, selectable) in panels.iter_mut() {
+    for (mut tile, target, mut source, mut sprite, selectable) in panels.iter_mut() {
         if target.activated != source.on {
             debug!("panel {} changed to {}", target.id, target.activated);
             source.on = target.activated;
@@ -70,33 +77,51 @@ fn target_changed(
             });
         }
 
-        if let Some(atlas) = &mut sprite.texture_atlas {
-            atlas.index = panel_sprite_index(target.connected, selectable);
+        let Some(tileset) = tilesets.get(&tile.tileset) else {
+            continue;
+        };
+        let index = tileset.index(&panel_sprite_tile(target, selectable));
+        if let (Some(atlas), Some(index)) = (&mut sprite.texture_atlas, index) {
+            atlas.index = index;
+        }
+        // the glow shows whether the panel is wired, whether or not it's selected
+        let light = tileset.light(&panel_tile(target));
+        if tile.light != light {
+            tile.light = light;
         }
     }
 }
 
 fn selection_changed(
     mut panels: Query<
-        (&mut Sprite, &Selectable, &ControlTarget),
+        (&Tile, &mut Sprite, &Selectable, &ControlTarget),
         (With<DoorPanel>, Changed<Selectable>),
     >,
+    tilesets: Res<Assets<Tileset>>,
 ) {
-    for (mut sprite, selectable, target) in panels.iter_mut() {
-        if let Some(atlas) = &mut sprite.texture_atlas {
-            atlas.index = panel_sprite_index(target.connected, selectable);
+    for (tile, mut sprite, selectable, target) in panels.iter_mut() {
+        let index = tilesets
+            .get(&tile.tileset)
+            .and_then(|tileset| tileset.index(&panel_sprite_tile(target, selectable)));
+        if let (Some(atlas), Some(index)) = (&mut sprite.texture_atlas, index) {
+            atlas.index = index;
         }
     }
 }
 
-fn panel_sprite_index(connected: bool, selectable: &Selectable) -> usize {
-    if connected && selectable.selected {
-        TuesdayTile::PanelEnabledSelected.into()
-    } else if connected && !selectable.selected {
-        TuesdayTile::PanelEnabled(1).into()
-    } else if !connected && selectable.selected {
-        TuesdayTile::PanelDisabledSelected.into()
+fn panel_sprite_tile(target: &ControlTarget, selectable: &Selectable) -> TuesdayTile {
+    match (target.connected, selectable.selected) {
+        (true, true) => TuesdayTile::PanelEnabledSelected,
+        (false, true) => TuesdayTile::PanelDisabledSelected,
+        _ => panel_tile(target),
+    }
+}
+
+/// The panel's tile while it isn't selected
+fn panel_tile(target: &ControlTarget) -> TuesdayTile {
+    if target.connected {
+        TuesdayTile::PanelEnabled(target.id)
     } else {
-        TuesdayTile::PanelDisabled(1).into()
+        TuesdayTile::PanelDisabled(target.id)
     }
 }
@@ -6,6 +6,7 @@ use rand::prelude::*;
 use rand_chacha::ChaCha8Rng;
 
 use crate::defs::GameLayer;
+use crate::keycard::Keycards;
 use crate::map::{NewMap, Tile, TileRole};
 use crate::seed::RngSeed;
 use crate::sprite_animation::SpriteAnimConfig;
@@ -36,6 +37,7 @@ fn spawn_player(
 
     commands.spawn((
         Player,
+        Keycards::default(),
         Sprite {
             image: asset_server.load("D2.png"),
             texture_atlas: Some(TextureAtlas {
@@ -97,7 +99,7 @@ fn player_keyboard_input(
 fn set_player_start(
     mut ev_newmap: EventReader<NewMap>,
     query: Query<(&GlobalTransform, &Tile)>,
-    mut player: Query<&mut Transform, With<Player>>,
+    mut player: Query<(&mut Transform, &mut LinearVelocity), With<Player>>,
     seed: Res<RngSeed>,
 ) {
     for _ in ev_newmap.read() {
@@ -116,7 +118,8 @@ fn set_player_start(
             *starting_points.choose(&mut rng).unwrap()
         };
 
-        let mut player = player.single_mut();
+        let (mut player, mut velocity) = player.single_mut();
+        velocity.0 = Vec2::ZERO;
 
         debug!(
             "moving player to {},{}",
@@ -3,7 +3,7 @@ use bevy::prelude::*;
 
 use crate::connections::SourceStateChanged;
 use crate::defs::{ControlLink, ControlSource, GameLayer};
-use crate::map::{Tile, TileRole, TuesdayTile};
+use crate::map::{Tile, TileRole, Tileset, TuesdayTile};
 use crate::selection::Selectable;
 
 #[derive(Component)]
@@ -71,38 +71,46 @@ fn press_switch(
 
 fn source_changed(
     mut switch: Query<
-        (&ControlSource, &Selectable, &mut Sprite),
+        (&Tile, &ControlSource, &Selectable, &mut Sprite),
         (Changed<ControlSource>, With<Switch>),
     >,
+    tilesets: Res<Assets<Tileset>>,
 ) {
-    for (source, selectable, mut sprite) in switch.iter_mut() {
-        if let Some(atlas) = &mut sprite.texture_atlas {
-            atlas.index = sprite_index(source, selectable);
+    for (tile, source, selectable, mut sprite) in switch.iter_mut() {
+        let index = tilesets
+            .get(&tile.tileset)
+            .and_then(|tileset| tileset.index(&sprite_tile(source, selectable)));
+        if let (Some(atlas), Some(index)) = (&mut sprite.texture_atlas, index) {
+            atlas.index = index;
         }
     }
 }
 
 fn selection_changed(
     mut switch: Query<
-        (&ControlSource, &Selectable, &mut Sprite),
+        (&Tile, &ControlSource, &Selectable, &mut Sprite),
         (Changed<Selectable>, With<Switch>),
     >,
+    tilesets: Res<Assets<Tileset>>,
 ) {
-    for (source, selectable, mut sprite) in switch.iter_mut() {
-        if let Some(atlas) = &mut sprite.texture_atlas {
-            atlas.index = sprite_index(source, selectable);
+    for (tile, source, selectable, mut sprite) in switch.iter_mut() {
+        let index = tilesets
+            .get(&tile.tileset)
+            .and_then(|tileset| tileset.index(&sprite_tile(source, selectable)));
+        if let (Some(atlas), Some(index)) = (&mut sprite.texture_atlas, index) {
+            atlas.index = index;
         }
     }
 }
 
-fn sprite_index(source: &ControlSource, selectable: &Selectable) -> usize {
+fn sprite_tile(source: &ControlSource, selectable: &Selectable) -> TuesdayTile {
     if source.on && selectable.selected {
-        TuesdayTile::SwitchSelectedRight.into()
+        TuesdayTile::SwitchSelectedRight
     } else if source.on && !selectable.selected {
-        TuesdayTile::SwitchRight(1).into()
+        TuesdayTile::SwitchRight(source.id)
     } else if !source.on && !selectable.selected {
-        TuesdayTile::SwitchLeft(1).into()
+        TuesdayTile::SwitchLeft(source.id)
     } else {
-        TuesdayTile::SwitchSelectedLeft.into()
+        TuesdayTile::SwitchSelectedLeft
     }
 }